@@ -0,0 +1,231 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::{Context, bail};
+use bidirected_adjacency_array::index::GraphIndexInteger;
+use clap::Parser;
+use log::{LevelFilter, info};
+use spqr_tree::graph::StaticGraph;
+
+use crate::{
+    gfa::read_gfa_file,
+    io_util::{ensure_stdio_used_at_most_once, write_optionally_compressed_file},
+    spqr_decomposition::{compute_spqr_decomposition, write_plain_spqr},
+    word_size::select_word_size,
+};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph to decompose.
+    /// Since the plain text format identifies links by their endpoints,
+    /// graphs with more than one link between the same two segments are rejected.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The output file for the SPQR decomposition in plain text format.
+    #[clap(long)]
+    spqr_out: PathBuf,
+
+    /// The integer size to use in all data structures.
+    /// Supported values are 8, 16, 32, and 64.
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    #[clap(long)]
+    word_size: Option<u8>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
+}
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once([cli.graph_gfa_in.as_path()], [cli.spqr_out.as_path()])?;
+
+    match select_word_size(&cli.graph_gfa_in, cli.word_size)? {
+        8 => run_with_word_size::<u8>(cli),
+        16 => run_with_word_size::<u16>(cli),
+        32 => run_with_word_size::<u32>(cli),
+        64 => run_with_word_size::<u64>(cli),
+        word_size => unreachable!("Unsupported word size: {word_size}"),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<()> {
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    // The plain text format identifies edges by their endpoints.
+    let mut endpoints = HashSet::new();
+    if let Some((u, v)) = graph
+        .iter_edges()
+        .map(|edge| {
+            let (u, v) = StaticGraph::edge_endpoints(&graph, edge);
+            (u.min(v), u.max(v))
+        })
+        .find(|&endpoints_key| !endpoints.insert(endpoints_key))
+    {
+        bail!(
+            "Segments {} and {} are connected by more than one link, \
+             which the plain text SPQR decomposition format cannot represent",
+            graph.node_name(u),
+            graph.node_name(v),
+        );
+    }
+
+    info!("Computing SPQR decomposition");
+    let spqr_decomposition = compute_spqr_decomposition(&graph)?;
+    info!(
+        "SPQR decomposition has {} components, {} blocks and {} SPQR nodes",
+        spqr_decomposition.component_count(),
+        spqr_decomposition.block_count(),
+        spqr_decomposition.spqr_node_count(),
+    );
+
+    info!("Writing SPQR decomposition to file {:?}", cli.spqr_out);
+    write_optionally_compressed_file(&cli.spqr_out, cli.compression_level, |writer| {
+        write_plain_spqr(&spqr_decomposition, writer).with_context(|| {
+            format!(
+                "I/O error while writing SPQR decomposition to file: {:?}",
+                cli.spqr_out
+            )
+        })
+    })
+    .with_context(|| {
+        format!(
+            "Failed to write SPQR decomposition to file: {:?}",
+            cli.spqr_out
+        )
+    })?;
+
+    info!("Finished");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use crate::{gfa::read_gfa, spqr_decomposition::compute_spqr_decomposition};
+
+    const TINY1_LN_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1_ln.gfa");
+    const TINY1_QUERY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1.query");
+
+    #[test]
+    fn decomposition_can_be_used_for_indexing() {
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-decompose-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_string();
+
+        super::run(super::Cli::parse_from([
+            "decompose",
+            "--graph-gfa-in",
+            TINY1_LN_GFA,
+            "--spqr-out",
+            &path("tiny1.spqr"),
+        ]))
+        .unwrap();
+        crate::index::run(crate::index::Cli::parse_from([
+            "index",
+            "--graph-gfa-in",
+            TINY1_LN_GFA,
+            "--spqr-in",
+            &path("tiny1.spqr"),
+            "--index-out",
+            &path("tiny1.index"),
+        ]))
+        .unwrap();
+        crate::query::run(crate::query::Cli::parse_from([
+            "query",
+            "--graph-gfa-in",
+            TINY1_LN_GFA,
+            "--index-in",
+            &path("tiny1.index"),
+            "--query-in",
+            TINY1_QUERY,
+            "--query-out",
+            &path("index.out"),
+        ]))
+        .unwrap();
+        crate::query::run(crate::query::Cli::parse_from([
+            "query",
+            "--graph-gfa-in",
+            TINY1_LN_GFA,
+            "--query-in",
+            TINY1_QUERY,
+            "--query-out",
+            &path("dijkstra.out"),
+        ]))
+        .unwrap();
+
+        let index_results = fs::read_to_string(path("index.out")).unwrap();
+        assert_eq!(index_results.lines().count(), 4);
+        assert_eq!(
+            index_results,
+            fs::read_to_string(path("dijkstra.out")).unwrap()
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn self_loop_on_cut_node_is_rejected() {
+        let graph = read_gfa::<u32>(
+            b"S\ta\tA\nS\tb\tA\nS\tc\tA\nS\td\tA\n\
+              L\ta\t+\tb\t+\t0M\nL\tb\t+\tc\t+\t0M\nL\tc\t+\ta\t+\t0M\n\
+              L\tc\t+\td\t+\t0M\nL\tc\t+\tc\t-\t0M\n"
+                .as_slice(),
+        )
+        .unwrap();
+        let error = compute_spqr_decomposition(&graph).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Segment c has a self-loop and is a cut node that lies in 2 blocks. \
+             The SPQR decomposition does not support self-loops on cut nodes.",
+        );
+    }
+
+    #[test]
+    fn multiple_links_between_two_segments_are_rejected() {
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-decompose-multi-link-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let graph_gfa = directory.join("multi_link.gfa");
+        let spqr_out = directory.join("multi_link.spqr");
+        fs::write(
+            &graph_gfa,
+            "S\ta\tA\nS\tb\tA\nS\tc\tA\n\
+             L\ta\t+\tb\t+\t0M\nL\ta\t-\tb\t-\t0M\nL\tb\t+\tc\t+\t0M\nL\tc\t+\ta\t+\t0M\n",
+        )
+        .unwrap();
+
+        let error = super::run(super::Cli::parse_from([
+            "decompose",
+            "--graph-gfa-in",
+            graph_gfa.to_str().unwrap(),
+            "--spqr-out",
+            spqr_out.to_str().unwrap(),
+        ]))
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Segments a and b are connected by more than one link, \
+             which the plain text SPQR decomposition format cannot represent",
+        );
+        assert!(!spqr_out.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use bidirected_adjacency_array::{
+    graph::{BidirectedAdjacencyArray, DirectedEdge},
+    index::{DirectedNodeIndex, GraphIndexInteger},
+    io::gfa1::{GfaEdgeData, GfaNodeData},
+};
+use spqr_shortest_path_index::{
+    gfa_graph_extensions::GfaNodeDataExt,
+    location::{GfaLocation, GfaNodeOffset},
+    path::{GfaPath, GfaPathLength, PathElement},
+};
+
+use crate::overlay::Overlay;
+
+/// Returns the distance from the start of the tail of the edge to the start of its head,
+/// which is the length of the tail minus the overlap of the edge.
+///
+/// Overlaps are never longer than the segments they connect, which is checked when reading the graph.
+pub fn edge_length<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    edge: &DirectedEdge<IndexType>,
+) -> GfaPathLength<IndexType> {
+    let from_length =
+        GfaNodeDataExt::<IndexType>::len(graph.node_data(edge.from().into_bidirected()));
+    let overlap = graph.directed_edge_data(edge.index()).data().overlap();
+    from_length - GfaPathLength::from_usize(overlap.into())
+}
+
+/// Dijkstra's algorithm on the graph, which subtracts the overlaps of the edges.
+///
+/// Unlike [`spqr_shortest_path_index::dijkstra::GfaDijkstra`], this supports graphs that are not blunt-ended.
+pub struct GfaDijkstra<'graph, IndexType: GraphIndexInteger, NodeData, EdgeData> {
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    state: SearchState<IndexType>,
+}
+
+/// Dijkstra's algorithm on the overlay, which skips the parts of the graph that are not needed to reach the targets.
+///
+/// This uses the same expansion rules as [`spqr_shortest_path_index::spqr_decomposition_overlay::dijkstra::OverlayDijkstra`],
+/// but subtracts the overlaps of the edges.
+/// The lengths of the overlay edges are the distances from the start of their tail to the end of their head.
+pub struct OverlayDijkstra<
+    'graph,
+    'spqr,
+    'overlay,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+> {
+    overlay: &'overlay Overlay<'graph, 'spqr, IndexType, NodeData, EdgeData>,
+    state: SearchState<IndexType>,
+}
+
+/// The open and closed lists of Dijkstra's algorithm, which are reused between searches.
+///
+/// The cost of a node is the distance from the start of the source node to its start.
+/// Since overlaps are never longer than the segments they connect, costs never decrease along a walk.
+///
+/// With overlaps, a walk can reach the start of a node before it passes the offset of the source in the source node.
+/// Whether a location of such a node lies after the source depends on the exact cost of the walk,
+/// so a longer walk may reach locations that a shorter one does not.
+/// Hence, a node is closed at most once for each cost below the source offset,
+/// and only once with a cost at or beyond the source offset, after which all of its locations lie after the source.
+struct SearchState<IndexType: GraphIndexInteger> {
+    open_list: BinaryHeap<Reverse<OpenNode<IndexType>>>,
+    /// The closed search nodes in the order in which they were closed.
+    closed_nodes: Vec<ClosedNode<IndexType>>,
+    closed_list: ClosedList<IndexType>,
+    /// For each target node, its closed search nodes in increasing order of cost.
+    target_closed_nodes: HashMap<DirectedNodeIndex<IndexType>, Vec<usize>>,
+    /// True if the last search skipped a walk because it was longer than the maximum distance.
+    cut_off: bool,
+}
+
+type OpenNode<IndexType> = (
+    GfaPathLength<IndexType>,
+    DirectedNodeIndex<IndexType>,
+    Option<usize>,
+);
+
+/// Finds the closed search nodes by their node and cost.
+struct ClosedList<IndexType> {
+    /// For each directed node, its closed search node whose walk has passed the source, if any.
+    past_source: Vec<Option<usize>>,
+    /// The directed nodes and costs of the closed search nodes whose walks have not passed the source.
+    before_source: HashSet<(DirectedNodeIndex<IndexType>, usize)>,
+}
+
+#[derive(Clone, Copy)]
+struct ClosedNode<IndexType> {
+    node: DirectedNodeIndex<IndexType>,
+    cost: GfaPathLength<IndexType>,
+    /// The predecessor search node on the shortest walk, or `None` if the node is a successor of the source node.
+    predecessor: Option<usize>,
+}
+
+impl<'graph, IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>
+    GfaDijkstra<'graph, IndexType, NodeData, EdgeData>
+{
+    pub fn new(graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>) -> Self {
+        Self {
+            graph,
+            state: SearchState::new(graph.node_count() * 2),
+        }
+    }
+
+    pub fn graph(&self) -> &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData> {
+        self.graph
+    }
+
+    /// Returns true if the last search skipped walks longer than its maximum distance.
+    /// Otherwise, the targets it did not find are unreachable.
+    pub fn is_cut_off(&self) -> bool {
+        self.state.cut_off
+    }
+
+    /// Computes the shortest paths from the source to all targets that are at most `max_distance` away.
+    ///
+    /// Nodes farther away than `max_distance` are never explored.
+    pub fn shortest_paths(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: Option<GfaPathLength<IndexType>>,
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        let graph = self.graph;
+        self.state.run(
+            source,
+            &target_nodes(source, targets),
+            max_cost(source, max_distance),
+            |from_node, _, expand| {
+                for outgoing_edge in graph.iter_outgoing_edges(from_node) {
+                    expand(outgoing_edge.to(), edge_length(graph, &outgoing_edge));
+                }
+            },
+        );
+        self.state.paths(graph, source, targets, max_distance)
+    }
+
+    /// Returns the distance from the start of `from` to the start of `to` over at least one edge,
+    /// and the nodes strictly between them on a shortest walk.
+    pub fn shortest_walk(
+        &mut self,
+        from: DirectedNodeIndex<IndexType>,
+        to: DirectedNodeIndex<IndexType>,
+    ) -> Option<(GfaPathLength<IndexType>, Vec<DirectedNodeIndex<IndexType>>)> {
+        let graph = self.graph;
+        self.state.run(
+            GfaLocation::new(from, GfaNodeOffset::from_usize(0)),
+            &HashMap::from([(to, 0)]),
+            usize::MAX,
+            |from_node, _, expand| {
+                for outgoing_edge in graph.iter_outgoing_edges(from_node) {
+                    expand(outgoing_edge.to(), edge_length(graph, &outgoing_edge));
+                }
+            },
+        );
+
+        let to = self.state.closed_list.past_source[to.into_usize()]?;
+        let cost = self.state.closed_nodes[to].cost;
+        let mut walk = self.state.backtrack(to);
+        walk.reverse();
+        Some((cost, walk))
+    }
+}
+
+impl<
+    'graph,
+    'spqr,
+    'overlay,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+> OverlayDijkstra<'graph, 'spqr, 'overlay, IndexType, NodeData, EdgeData>
+{
+    pub fn new(overlay: &'overlay Overlay<'graph, 'spqr, IndexType, NodeData, EdgeData>) -> Self {
+        Self {
+            overlay,
+            state: SearchState::new(overlay.graph().node_count() * 2),
+        }
+    }
+
+    /// Returns true if the last search skipped walks longer than its maximum distance.
+    /// Otherwise, the targets it did not find are unreachable.
+    pub fn is_cut_off(&self) -> bool {
+        self.state.cut_off
+    }
+
+    /// Computes the shortest paths from the source to all targets that are at most `max_distance` away.
+    ///
+    /// Nodes farther away than `max_distance` are never explored.
+    /// The paths jump over the nodes that are contracted into overlay edges.
+    pub fn shortest_paths(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: Option<GfaPathLength<IndexType>>,
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        let overlay = self.overlay;
+        let graph = overlay.graph();
+        let spqr_decomposition = overlay.spqr_decomposition();
+
+        // Only the blocks and SPQR-tree nodes containing the source or a target in the same component are expanded below the block-cut tree level.
+        let component = spqr_decomposition.node_component_index(source.node().into_bidirected());
+        let mut active_blocks = HashSet::new();
+        let mut active_spqr_nodes = HashSet::new();
+        for location in targets.iter().chain([&source]) {
+            let node = location.node().into_bidirected();
+            if spqr_decomposition.node_component_index(node) == component {
+                active_blocks.extend(spqr_decomposition.node_block_indices(node));
+                active_spqr_nodes.extend(spqr_decomposition.node_spqr_node_indices(node));
+            }
+        }
+
+        // Overlay edges span both of their nodes, but the search measures distances between the starts of nodes.
+        let overlay_edge_length =
+            |to_node: DirectedNodeIndex<IndexType>, length: GfaPathLength<IndexType>| {
+                length
+                    - GfaNodeDataExt::<IndexType>::len(graph.node_data(to_node.into_bidirected()))
+            };
+
+        self.state.run(
+            source,
+            &target_nodes(source, targets),
+            max_cost(source, max_distance),
+            |from_node, past_source, expand| {
+                let from_bidirected_node = from_node.into_bidirected();
+
+                // Before passing the source, a walk may need to be longer than the shortest walks stored in the overlay,
+                // so it follows the edges of the graph.
+                // Its nodes then act as additional sources for the walks that continue after passing the source.
+                if !past_source {
+                    active_blocks
+                        .extend(spqr_decomposition.node_block_indices(from_bidirected_node));
+                    active_spqr_nodes
+                        .extend(spqr_decomposition.node_spqr_node_indices(from_bidirected_node));
+                    for outgoing_edge in graph.iter_outgoing_edges(from_node) {
+                        expand(outgoing_edge.to(), edge_length(graph, &outgoing_edge));
+                    }
+                    return;
+                }
+
+                if spqr_decomposition.is_cut_node(from_bidirected_node) {
+                    for (to_node, length) in overlay.iter_outgoing_block_cut_tree_edges(from_node) {
+                        expand(to_node, overlay_edge_length(to_node, length));
+                    }
+                }
+
+                if spqr_decomposition.has_incident_virtual_edge(from_bidirected_node)
+                    && spqr_decomposition
+                        .node_block_indices(from_bidirected_node)
+                        .any(|block| active_blocks.contains(&block))
+                {
+                    for (to_node, length) in overlay.iter_outgoing_spqr_tree_edges(from_node) {
+                        if spqr_decomposition
+                            .node_block_indices(to_node.into_bidirected())
+                            .any(|block| active_blocks.contains(&block))
+                        {
+                            expand(to_node, overlay_edge_length(to_node, length));
+                        }
+                    }
+                }
+
+                if spqr_decomposition
+                    .node_spqr_node_indices(from_bidirected_node)
+                    .any(|spqr_node| active_spqr_nodes.contains(&spqr_node))
+                {
+                    for outgoing_edge in graph.iter_outgoing_edges(from_node) {
+                        let to_node = outgoing_edge.to();
+                        if spqr_decomposition
+                            .node_spqr_node_indices(to_node.into_bidirected())
+                            .any(|spqr_node| active_spqr_nodes.contains(&spqr_node))
+                        {
+                            expand(to_node, edge_length(graph, &outgoing_edge));
+                        }
+                    }
+                }
+            },
+        );
+        self.state.paths(graph, source, targets, max_distance)
+    }
+}
+
+impl<IndexType: GraphIndexInteger> ClosedList<IndexType> {
+    /// Returns true if the node is closed with the given cost,
+    /// where all costs at or beyond the source offset count as the same.
+    fn is_closed(
+        &self,
+        node: DirectedNodeIndex<IndexType>,
+        cost: usize,
+        source_offset: usize,
+    ) -> bool {
+        if cost >= source_offset {
+            self.past_source[node.into_usize()].is_some()
+        } else {
+            self.before_source.contains(&(node, cost))
+        }
+    }
+}
+
+/// Returns the target nodes together with the smallest cost at which all their target locations are after the source.
+fn target_nodes<IndexType: GraphIndexInteger>(
+    source: GfaLocation<IndexType>,
+    targets: &[GfaLocation<IndexType>],
+) -> HashMap<DirectedNodeIndex<IndexType>, usize> {
+    let mut target_nodes = HashMap::new();
+    for target in targets {
+        let min_cost = source
+            .offset()
+            .into_usize()
+            .saturating_sub(target.offset().into_usize());
+        let entry = target_nodes.entry(target.node()).or_insert(0);
+        *entry = min_cost.max(*entry);
+    }
+    target_nodes
+}
+
+/// Returns the largest cost of a node whose start is at most `max_distance` away from the source.
+fn max_cost<IndexType: GraphIndexInteger>(
+    source: GfaLocation<IndexType>,
+    max_distance: Option<GfaPathLength<IndexType>>,
+) -> usize {
+    max_distance.map_or(usize::MAX, |max_distance| {
+        max_distance.into_usize() + source.offset().into_usize()
+    })
+}
+
+impl<IndexType: GraphIndexInteger> SearchState<IndexType> {
+    fn new(directed_node_count: usize) -> Self {
+        Self {
+            open_list: BinaryHeap::new(),
+            closed_nodes: Vec::new(),
+            closed_list: ClosedList {
+                past_source: vec![None; directed_node_count],
+                before_source: HashSet::new(),
+            },
+            target_closed_nodes: HashMap::new(),
+            cut_off: false,
+        }
+    }
+
+    /// Runs Dijkstra's algorithm from the start of the source node
+    /// until all target nodes are closed with at least their given cost, or the next node costs more than `max_cost`.
+    ///
+    /// Like the upstream searches, we start from the successors of the source node to allow paths that return to the source node.
+    /// The function `successors` calls its third argument for each successor of a node
+    /// together with the distance from the start of the node to the start of the successor.
+    /// Its second argument is true if the walk to the node has passed the source.
+    fn run(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        target_nodes: &HashMap<DirectedNodeIndex<IndexType>, usize>,
+        max_cost: usize,
+        mut successors: impl FnMut(
+            DirectedNodeIndex<IndexType>,
+            bool,
+            &mut dyn FnMut(DirectedNodeIndex<IndexType>, GfaPathLength<IndexType>),
+        ),
+    ) {
+        self.open_list.clear();
+        for closed_node in self.closed_nodes.drain(..) {
+            self.closed_list.past_source[closed_node.node.into_usize()] = None;
+        }
+        self.closed_list.before_source.clear();
+        self.target_closed_nodes.clear();
+        self.cut_off = false;
+        let source_offset = source.offset().into_usize();
+        let mut remaining_target_nodes: HashSet<_> = target_nodes.keys().copied().collect();
+
+        let (open_list, cut_off) = (&mut self.open_list, &mut self.cut_off);
+        successors(
+            source.node(),
+            source_offset == 0,
+            &mut |to_node, edge_length| {
+                if edge_length.into_usize() <= max_cost {
+                    open_list.push(Reverse((edge_length, to_node, None)));
+                } else {
+                    *cut_off = true;
+                }
+            },
+        );
+
+        while !remaining_target_nodes.is_empty()
+            && let Some(Reverse((cost, node, predecessor))) = self.open_list.pop()
+        {
+            if self
+                .closed_list
+                .is_closed(node, cost.into_usize(), source_offset)
+            {
+                continue;
+            }
+            let past_source = cost.into_usize() >= source_offset;
+            let current_closed_node = self.closed_nodes.len();
+            self.closed_nodes.push(ClosedNode {
+                node,
+                cost,
+                predecessor,
+            });
+            if past_source {
+                self.closed_list.past_source[node.into_usize()] = Some(current_closed_node);
+            } else {
+                self.closed_list
+                    .before_source
+                    .insert((node, cost.into_usize()));
+            }
+            if let Some(&min_cost) = target_nodes.get(&node) {
+                self.target_closed_nodes
+                    .entry(node)
+                    .or_default()
+                    .push(current_closed_node);
+                if cost.into_usize() >= min_cost {
+                    remaining_target_nodes.remove(&node);
+                }
+            }
+
+            let (open_list, closed_list, cut_off) =
+                (&mut self.open_list, &self.closed_list, &mut self.cut_off);
+            successors(node, past_source, &mut |to_node, edge_length| {
+                // Summing up in `usize` avoids overflows beyond `max_cost`.
+                let cost = cost.into_usize() + edge_length.into_usize();
+                if cost > max_cost {
+                    *cut_off = true;
+                } else if !closed_list.is_closed(to_node, cost, source_offset) {
+                    open_list.push(Reverse((
+                        GfaPathLength::from_usize(cost),
+                        to_node,
+                        Some(current_closed_node),
+                    )));
+                }
+            });
+        }
+    }
+
+    /// Returns the nodes before the closed node on its shortest walk from the source node, from the closed node backwards.
+    /// Neither the node of the closed node nor the source node are included.
+    fn backtrack(&self, closed_node: usize) -> Vec<DirectedNodeIndex<IndexType>> {
+        let mut predecessors = Vec::new();
+        let mut current_closed_node = closed_node;
+        while let Some(predecessor) = self.closed_nodes[current_closed_node].predecessor {
+            predecessors.push(self.closed_nodes[predecessor].node);
+            current_closed_node = predecessor;
+        }
+        predecessors
+    }
+
+    /// Returns the shortest paths from the source to all targets that are at most `max_distance` away,
+    /// using the closed nodes of the last run.
+    ///
+    /// A target is reached by the shortest walk to its node that passes the source before reaching the target.
+    /// A target that is reached by a longer walk marks the search as cut off.
+    fn paths<NodeData: GfaNodeData, EdgeData>(
+        &mut self,
+        graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: Option<GfaPathLength<IndexType>>,
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        let full_node = |node: DirectedNodeIndex<IndexType>| -> GfaNodeOffset<IndexType> {
+            GfaNodeDataExt::<IndexType>::len(graph.node_data(node.into_bidirected())).into_offset()
+        };
+
+        targets
+            .iter()
+            .filter_map(|&target| {
+                let inner_distance = (target.node() == source.node()
+                    && target.offset() >= source.offset())
+                .then(|| target.offset() - source.offset());
+                // The target node is closed in increasing order of cost, so the first walk that passes the source is the shortest.
+                let outer = self
+                    .target_closed_nodes
+                    .get(&target.node())
+                    .into_iter()
+                    .flatten()
+                    .find_map(|&closed_node| {
+                        (self.closed_nodes[closed_node].cost.into_usize()
+                            + target.offset().into_usize())
+                        .checked_sub(source.offset().into_usize())
+                        .map(|distance| (GfaPathLength::from_usize(distance), closed_node))
+                    });
+
+                let path = match (inner_distance, outer) {
+                    (Some(inner_distance), outer)
+                        if outer
+                            .is_none_or(|(outer_distance, _)| inner_distance <= outer_distance) =>
+                    {
+                        GfaPath::new(
+                            vec![PathElement::new(
+                                source.node(),
+                                source.offset(),
+                                target.offset(),
+                            )],
+                            inner_distance,
+                        )
+                    }
+                    (_, Some((outer_distance, target_closed_node))) => {
+                        let mut path = vec![PathElement::new(
+                            target.node(),
+                            GfaNodeOffset::from_usize(0),
+                            target.offset(),
+                        )];
+                        path.extend(self.backtrack(target_closed_node).into_iter().map(|node| {
+                            PathElement::new(node, GfaNodeOffset::from_usize(0), full_node(node))
+                        }));
+                        path.push(PathElement::new(
+                            source.node(),
+                            source.offset(),
+                            full_node(source.node()),
+                        ));
+                        path.reverse();
+                        GfaPath::new(path, outer_distance)
+                    }
+                    (_, None) => return None,
+                };
+
+                if max_distance.is_some_and(|max_distance| path.length() > max_distance) {
+                    self.cut_off = true;
+                    return None;
+                }
+                Some((target, path))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,495 @@
+use std::{collections::HashSet, io::Write, path::PathBuf};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray,
+    index::{DirectedNodeIndex, GraphIndexInteger, NodeIndex},
+    io::gfa1::PlainGfaEdgeData,
+};
+use clap::{Parser, ValueEnum};
+use itertools::Itertools;
+use log::{LevelFilter, info};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use spqr_shortest_path_index::location::{GfaLocation, GfaNodeOffset};
+use spqr_tree::graph::StaticGraph;
+
+use crate::{
+    gfa::{GfaSegmentData, read_gfa_file},
+    io_util::{create_optionally_compressed_file, ensure_stdio_used_at_most_once},
+    spqr_decomposition::load_or_compute_spqr_decomposition,
+    word_size::select_word_size,
+};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph to sample the query locations from.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The SPQR decomposition in plain text format, used with `--restrict-to block`.
+    /// If it is not given, then it is computed from the graph.
+    #[clap(long)]
+    spqr_in: Option<PathBuf>,
+
+    /// The output file for the queries, in the format of the query input file of `biopath query`.
+    #[clap(long)]
+    query_out: PathBuf,
+
+    /// The number of query lines to generate.
+    #[clap(long)]
+    query_count: usize,
+
+    /// The number of target locations of each query line.
+    /// The targets of a line are pairwise distinct.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    targets_per_query: u32,
+
+    /// How the locations are distributed over the graph.
+    #[clap(long, value_enum, default_value_t = LocationDistribution::Nodes)]
+    distribution: LocationDistribution,
+
+    /// The orientations of the locations.
+    #[clap(long, value_enum, default_value_t = OrientationDistribution::Random)]
+    orientation: OrientationDistribution,
+
+    /// Sample the targets of each query from the same part of the graph as its source.
+    #[clap(long, value_enum, default_value_t = Restriction::None)]
+    restrict_to: Restriction,
+
+    /// The seed of the random number generator.
+    /// The same seed, graph and options always generate the same queries.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+
+    /// The integer size to use in all data structures.
+    /// Supported values are 8, 16, 32, and 64.
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    #[clap(long)]
+    word_size: Option<u8>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LocationDistribution {
+    /// Choose a node uniformly, and then an offset on the node uniformly.
+    Nodes,
+    /// Choose an offset uniformly among the offsets of all nodes, such that long nodes are chosen more often.
+    Bases,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OrientationDistribution {
+    /// Choose the forward or reverse orientation with equal probability.
+    Random,
+    /// Always choose the forward orientation.
+    Forward,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Restriction {
+    /// Sample the targets from the whole graph.
+    None,
+    /// Sample the targets from the connected component of the source.
+    Component,
+    /// Sample the targets from a block of the source.
+    /// If the source is a cut node, then one of its blocks is chosen uniformly.
+    Block,
+}
+
+/// The number of sources that are tried for each query before giving up,
+/// if the part of the graph of the source is too small for the requested number of distinct targets.
+const MAX_SOURCE_ATTEMPTS: usize = 1000;
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once(
+        [Some(cli.graph_gfa_in.as_path()), cli.spqr_in.as_deref()]
+            .into_iter()
+            .flatten(),
+        [cli.query_out.as_path()],
+    )?;
+
+    match select_word_size(&cli.graph_gfa_in, cli.word_size)? {
+        8 => run_with_word_size::<u8>(cli),
+        16 => run_with_word_size::<u16>(cli),
+        32 => run_with_word_size::<u32>(cli),
+        64 => run_with_word_size::<u64>(cli),
+        word_size => unreachable!("Unsupported word size: {word_size}"),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<()> {
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+    if graph.node_count() == 0 {
+        anyhow::bail!("Cannot generate queries for an empty graph");
+    }
+
+    let graph_sampler = LocationSampler::new(&graph, graph.iter_nodes());
+    // For each node, the samplers of the parts of the graph that its targets may be sampled from.
+    let mut target_samplers = Vec::new();
+    let mut node_target_samplers = vec![Vec::new(); graph.node_count()];
+    match cli.restrict_to {
+        Restriction::None => {
+            target_samplers.push(graph_sampler.clone());
+            node_target_samplers.fill(vec![0]);
+        }
+        Restriction::Component | Restriction::Block => {
+            let spqr_decomposition =
+                load_or_compute_spqr_decomposition(&graph, cli.spqr_in.as_deref())?;
+            let parts = if cli.restrict_to == Restriction::Component {
+                spqr_decomposition
+                    .iter_components()
+                    .map(|(_, component)| component.iter_nodes().collect_vec())
+                    .collect_vec()
+            } else {
+                spqr_decomposition
+                    .iter_blocks()
+                    .map(|(_, block)| block.iter_nodes().collect_vec())
+                    .collect_vec()
+            };
+
+            for nodes in parts {
+                for node in &nodes {
+                    node_target_samplers[node.into_usize()].push(target_samplers.len());
+                }
+                target_samplers.push(LocationSampler::new(&graph, nodes.into_iter()));
+            }
+            // Nodes without edges are not part of any block.
+            for (node_index, samplers) in node_target_samplers.iter_mut().enumerate() {
+                if samplers.is_empty() {
+                    samplers.push(target_samplers.len());
+                    target_samplers.push(LocationSampler::new(
+                        &graph,
+                        [NodeIndex::from_usize(node_index)].into_iter(),
+                    ));
+                }
+            }
+        }
+    }
+
+    info!(
+        "Generating {} queries with {} target(s) each and seed {}",
+        cli.query_count, cli.targets_per_query, cli.seed
+    );
+    let mut rng = StdRng::seed_from_u64(cli.seed);
+    let targets_per_query = usize::try_from(cli.targets_per_query).unwrap();
+    let mut writer = create_optionally_compressed_file(&cli.query_out, cli.compression_level)
+        .with_context(|| format!("Failed to create query output file {:?}", cli.query_out))?;
+    for _ in 0..cli.query_count {
+        let (source, target_sampler) = (0..MAX_SOURCE_ATTEMPTS)
+            .map(|_| {
+                let source = graph_sampler.sample(&mut rng, cli.distribution, cli.orientation);
+                let samplers = &node_target_samplers[source.node().into_bidirected().into_usize()];
+                let target_sampler = &target_samplers[samplers[rng.random_range(0..samplers.len())]];
+                (source, target_sampler)
+            })
+            .find(|(_, target_sampler)| {
+                target_sampler.location_count(cli.orientation) >= targets_per_query as u64
+            })
+            .with_context(|| {
+                format!(
+                    "Failed to find a source with at least {} distinct target locations after {} attempts",
+                    targets_per_query, MAX_SOURCE_ATTEMPTS
+                )
+            })?;
+
+        let mut targets = HashSet::new();
+        write_location(&mut writer, &graph, source)
+            .with_context(|| format!("Failed to write query file {:?}", cli.query_out))?;
+        while targets.len() < targets_per_query {
+            let target = target_sampler.sample(&mut rng, cli.distribution, cli.orientation);
+            if targets.insert(target) {
+                write!(writer, "\t")
+                    .map_err(anyhow::Error::from)
+                    .and_then(|()| write_location(&mut writer, &graph, target))
+                    .with_context(|| format!("Failed to write query file {:?}", cli.query_out))?;
+            }
+        }
+        writeln!(writer)
+            .with_context(|| format!("Failed to write query file {:?}", cli.query_out))?;
+    }
+    writer
+        .finish()
+        .with_context(|| format!("Failed to write query file {:?}", cli.query_out))?;
+
+    info!("Finished");
+    Ok(())
+}
+
+fn write_location<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    location: GfaLocation<IndexType>,
+) -> anyhow::Result<()> {
+    write!(
+        writer,
+        "{}\t{}\t{}",
+        graph.node_name(location.node().into_bidirected()),
+        if location.node().is_forward() {
+            "+"
+        } else {
+            "-"
+        },
+        location.offset(),
+    )?;
+    Ok(())
+}
+
+/// Samples random locations from a set of nodes.
+#[derive(Clone)]
+pub(crate) struct LocationSampler<IndexType> {
+    nodes: Vec<NodeIndex<IndexType>>,
+    /// For each node, the number of offsets of all nodes up to and including it.
+    /// A node of length `l` has the `l + 1` offsets `0..=l`.
+    cumulative_offset_counts: Vec<u64>,
+}
+
+impl<IndexType: GraphIndexInteger> LocationSampler<IndexType> {
+    pub(crate) fn new(
+        graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+        nodes: impl Iterator<Item = NodeIndex<IndexType>>,
+    ) -> Self {
+        let nodes = nodes.collect_vec();
+        let cumulative_offset_counts = nodes
+            .iter()
+            .scan(0, |offset_count, &node| {
+                *offset_count += node_length(graph, node) + 1;
+                Some(*offset_count)
+            })
+            .collect();
+        Self {
+            nodes,
+            cumulative_offset_counts,
+        }
+    }
+
+    /// The number of distinct locations that can be sampled.
+    fn location_count(&self, orientation: OrientationDistribution) -> u64 {
+        let offset_count = self.cumulative_offset_counts.last().copied().unwrap_or(0);
+        match orientation {
+            OrientationDistribution::Random => 2 * offset_count,
+            OrientationDistribution::Forward => offset_count,
+        }
+    }
+
+    pub(crate) fn sample(
+        &self,
+        rng: &mut impl Rng,
+        distribution: LocationDistribution,
+        orientation: OrientationDistribution,
+    ) -> GfaLocation<IndexType> {
+        let node_index = match distribution {
+            LocationDistribution::Nodes => rng.random_range(0..self.nodes.len()),
+            LocationDistribution::Bases => {
+                let offset_index =
+                    rng.random_range(0..*self.cumulative_offset_counts.last().unwrap());
+                self.cumulative_offset_counts
+                    .partition_point(|&offset_count| offset_count <= offset_index)
+            }
+        };
+        let offset_count = self.cumulative_offset_counts[node_index]
+            - node_index
+                .checked_sub(1)
+                .map_or(0, |previous| self.cumulative_offset_counts[previous]);
+        let forward = match orientation {
+            OrientationDistribution::Random => rng.random(),
+            OrientationDistribution::Forward => true,
+        };
+
+        GfaLocation::new(
+            DirectedNodeIndex::from_bidirected(self.nodes[node_index], forward),
+            GfaNodeOffset::from_usize(rng.random_range(0..offset_count).try_into().unwrap()),
+        )
+    }
+}
+
+fn node_length<IndexType: GraphIndexInteger>(
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    node: NodeIndex<IndexType>,
+) -> u64 {
+    graph.node_data(node).length() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, path::Path};
+
+    use bidirected_adjacency_array::index::NodeIndex;
+    use clap::Parser;
+    use itertools::Itertools;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::{LocationDistribution, LocationSampler, OrientationDistribution};
+    use crate::gfa::read_gfa;
+
+    /// Two components, where the first consists of two triangles that share the cut node c.
+    const GRAPH_GFA: &str = "S\ta\tAA\nS\tb\tAAA\nS\tc\tA\nS\td\tAA\nS\te\tAAAA\n\
+                             S\tx\tAAA\nS\ty\tA\nS\tz\tAA\n\
+                             L\ta\t+\tb\t+\t0M\nL\tb\t+\tc\t+\t0M\nL\tc\t+\ta\t+\t0M\n\
+                             L\tc\t+\td\t+\t0M\nL\td\t+\te\t+\t0M\nL\te\t+\tc\t+\t0M\n\
+                             L\tx\t+\ty\t+\t0M\nL\ty\t+\tz\t+\t0M\nL\tz\t+\tx\t+\t0M\n";
+    const BLOCKS: [&[&str]; 3] = [&["a", "b", "c"], &["c", "d", "e"], &["x", "y", "z"]];
+    const COMPONENTS: [&[&str]; 2] = [&["a", "b", "c", "d", "e"], &["x", "y", "z"]];
+
+    #[test]
+    fn sampled_locations_lie_on_the_sampled_nodes() {
+        let graph = read_gfa::<u32>(GRAPH_GFA.as_bytes()).unwrap();
+        let nodes = [1, 4, 5].map(NodeIndex::from_usize);
+        let sampler = LocationSampler::new(&graph, nodes.into_iter());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for distribution in [LocationDistribution::Nodes, LocationDistribution::Bases] {
+            let mut offsets = HashSet::new();
+            for _ in 0..1000 {
+                let location =
+                    sampler.sample(&mut rng, distribution, OrientationDistribution::Random);
+                let node = location.node().into_bidirected();
+                assert!(nodes.contains(&node));
+                assert!(location.offset().into_usize() <= graph.node_data(node).length());
+                offsets.insert((node, location.offset().into_usize()));
+            }
+            // Both ends of each node are sampled.
+            assert_eq!(offsets.len(), 3 + 1 + 4 + 1 + 3 + 1);
+
+            for _ in 0..100 {
+                let location =
+                    sampler.sample(&mut rng, distribution, OrientationDistribution::Forward);
+                assert!(location.node().is_forward());
+            }
+        }
+    }
+
+    /// Generates queries for [`GRAPH_GFA`] and returns the locations of each line, starting with the source.
+    fn generate_queries(directory: &Path, args: &[&str]) -> Vec<Vec<String>> {
+        let graph_gfa_in = directory.join("graph.gfa");
+        let query_out = directory.join("queries");
+        fs::write(&graph_gfa_in, GRAPH_GFA).unwrap();
+
+        super::run(super::Cli::parse_from(
+            [
+                "generate-queries",
+                "--graph-gfa-in",
+                graph_gfa_in.to_str().unwrap(),
+                "--query-out",
+                query_out.to_str().unwrap(),
+            ]
+            .into_iter()
+            .chain(args.iter().copied()),
+        ))
+        .unwrap();
+
+        fs::read_to_string(query_out)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                line.split('\t')
+                    .chunks(3)
+                    .into_iter()
+                    .map(|mut location| location.join("\t"))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn node_name(location: &str) -> &str {
+        location.split('\t').next().unwrap()
+    }
+
+    #[test]
+    fn targets_are_distinct() {
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-generate-queries-distinct-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        let queries = generate_queries(
+            &directory,
+            &["--query-count", "100", "--targets-per-query", "20"],
+        );
+        assert_eq!(queries.len(), 100);
+        for locations in queries {
+            assert_eq!(locations.len(), 21);
+            assert!(locations[1..].iter().all_unique());
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn targets_are_restricted_to_the_part_of_the_source() {
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-generate-queries-restrict-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        for (restriction, parts) in [("block", BLOCKS.as_slice()), ("component", &COMPONENTS)] {
+            let queries = generate_queries(
+                &directory,
+                &[
+                    "--query-count",
+                    "100",
+                    "--targets-per-query",
+                    "3",
+                    "--restrict-to",
+                    restriction,
+                ],
+            );
+            assert_eq!(queries.len(), 100);
+            for locations in queries {
+                assert!(
+                    parts.iter().any(|part| locations
+                        .iter()
+                        .all(|location| part.contains(&node_name(location)))),
+                    "The locations {locations:?} are not restricted to a single {restriction}",
+                );
+            }
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn same_seed_generates_same_queries() {
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-generate-queries-seed-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+
+        let args = |seed| {
+            [
+                "--query-count",
+                "100",
+                "--targets-per-query",
+                "2",
+                "--distribution",
+                "bases",
+                "--restrict-to",
+                "block",
+                "--seed",
+                seed,
+            ]
+        };
+        let queries = generate_queries(&directory, &args("42"));
+        assert_eq!(queries, generate_queries(&directory, &args("42")));
+        assert_ne!(queries, generate_queries(&directory, &args("43")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
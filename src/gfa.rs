@@ -0,0 +1,726 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::BufRead,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::{BidirectedAdjacencyArray, BidirectedEdge},
+    index::{EdgeIndex, GraphIndexInteger, NodeIndex},
+    io::gfa1::{GfaEdgeData, GfaNodeData, PlainGfaEdgeData},
+};
+use log::warn;
+use spqr_tree::graph::StaticGraph;
+use tagged_vec::TaggedVec;
+
+use crate::io_util::read_optionally_compressed_file;
+
+/// The data of a segment, whose sequence may be given only by its length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GfaSegmentData {
+    name: String,
+    sequence: SegmentSequence,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum SegmentSequence {
+    Given(String),
+    /// Only the length of the sequence is known.
+    /// The placeholder is shared by all such segments of the graph, and its prefix of the given length has the length of the sequence.
+    Unknown {
+        placeholder: Arc<str>,
+        length: usize,
+    },
+}
+
+impl GfaSegmentData {
+    /// Returns the length of the segment, which is known even if its sequence is not.
+    pub fn length(&self) -> usize {
+        match &self.sequence {
+            SegmentSequence::Given(sequence) => sequence.len(),
+            SegmentSequence::Unknown { length, .. } => *length,
+        }
+    }
+}
+
+impl GfaNodeData for GfaSegmentData {
+    fn name(&'_ self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.name)
+    }
+
+    /// Upstream computes the length of a segment from its sequence,
+    /// so a segment without a sequence returns a placeholder of its length whose characters carry no meaning.
+    /// Use [`GfaSegmentData::length`] to get the length of a segment without reading its sequence.
+    fn sequence(&'_ self) -> Cow<'_, str> {
+        match &self.sequence {
+            SegmentSequence::Given(sequence) => Cow::Borrowed(sequence),
+            SegmentSequence::Unknown {
+                placeholder,
+                length,
+            } => Cow::Borrowed(&placeholder[..*length]),
+        }
+    }
+}
+
+/// Reads the graph from the GFA file, which may be in GFA1 or GFA2 format.
+///
+/// Segments without a sequence (`*`) take their length from their `LN` tag in GFA1 and from their length field in GFA2.
+/// GFA2 edges that are dovetail overlaps become adjacencies, while containments and other alignments are reported and ignored.
+/// The overlap of each link is read from its CIGAR string,
+/// and an error is returned if an overlap is longer than one of the segments it connects.
+pub fn read_gfa_file<IndexType: GraphIndexInteger>(
+    graph_gfa_in: &Path,
+) -> anyhow::Result<BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>> {
+    let graph = read_optionally_compressed_file(graph_gfa_in, |reader| {
+        read_gfa(reader).with_context(|| format!("Failed to parse GFA file {:?}", graph_gfa_in))
+    })
+    .with_context(|| format!("Failed to read GFA file: {:?}", graph_gfa_in))?;
+    check_overlaps(&graph).with_context(|| format!("Invalid GFA file {:?}", graph_gfa_in))?;
+    Ok(graph)
+}
+
+/// Parses a GFA file like [`BidirectedAdjacencyArray::read_gfa1`],
+/// but reads overlaps from full CIGAR strings, segment lengths from `LN` tags, and also the GFA2 records.
+///
+/// The lines of both versions may be mixed, since they are told apart by their type and their columns.
+pub(crate) fn read_gfa<IndexType: GraphIndexInteger>(
+    reader: impl BufRead,
+) -> anyhow::Result<BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>> {
+    let mut node_name_to_node = HashMap::new();
+    // The sequences of the segments, or their lengths if they have no sequence.
+    let mut segments = TaggedVec::<NodeIndex<IndexType>, (String, Result<String, usize>)>::new();
+    let mut edges = TaggedVec::<EdgeIndex<IndexType>, _>::new();
+    let mut unsupported_line_types = HashSet::new();
+    let mut is_header_allowed = true;
+    // The first line of each kind of ignored GFA2 record, and their number.
+    let mut containments = (None, 0);
+    let mut internal_alignments = (None, 0);
+    let mut gaps = (None, 0);
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let columns = line.trim().split('\t').collect::<Vec<_>>();
+        if columns[0] != "H" {
+            is_header_allowed = false;
+        }
+
+        match columns[0] {
+            "H" => {
+                if !is_header_allowed {
+                    anyhow::bail!("A header line was found after other lines");
+                }
+                if let Some(version) = columns[1..]
+                    .iter()
+                    .find_map(|tag| tag.strip_prefix("VN:Z:"))
+                    && !["1.0", "2.0"].contains(&version)
+                {
+                    warn!("Unsupported GFA version: {}", version);
+                }
+            }
+
+            "S" => {
+                let name = columns
+                    .get(1)
+                    .with_context(|| format!("S line {} is missing the name", line_index + 1))?;
+                // GFA1 sequences are never numbers, so a number in the third column is the length field of GFA2.
+                let (sequence, length) = if columns.len() >= 4
+                    && !columns[2].is_empty()
+                    && columns[2].bytes().all(|byte| byte.is_ascii_digit())
+                {
+                    let length = columns[2].parse().with_context(|| {
+                        format!("Invalid segment length in S line {}", line_index + 1)
+                    })?;
+                    (columns[3], Some(length))
+                } else {
+                    let length = segment_length_tag(columns.get(3..).unwrap_or_default())
+                        .with_context(|| format!("Invalid LN tag in S line {}", line_index + 1))?;
+                    (columns.get(2).copied().unwrap_or("*"), length)
+                };
+                let sequence = match length {
+                    _ if sequence != "*" && !sequence.is_empty() => {
+                        if let Some(length) = length
+                            && length != sequence.len()
+                        {
+                            anyhow::bail!(
+                                "Segment {name} in S line {} has a sequence of length {}, but its given length is {length}",
+                                line_index + 1,
+                                sequence.len(),
+                            );
+                        }
+                        Ok(sequence.to_string())
+                    }
+                    Some(length) => Err(length),
+                    None => anyhow::bail!(
+                        "Segment {name} in S line {} has neither a sequence nor an LN tag, so its length is unknown",
+                        line_index + 1,
+                    ),
+                };
+                let node = segments.push((name.to_string(), sequence));
+                node_name_to_node.insert(name.to_string(), node);
+            }
+
+            "L" => {
+                let [from_name, from_sign, to_name, to_sign] = [1, 2, 3, 4].map(|column| {
+                    columns.get(column).with_context(|| {
+                        format!("L line {} is missing the edge endpoints", line_index + 1)
+                    })
+                });
+                let node = |name: anyhow::Result<&&str>| -> anyhow::Result<_> {
+                    let name = name?;
+                    node_name_to_node.get(*name).copied().with_context(|| {
+                        format!("Unknown node name {name:?} in L line {}", line_index + 1)
+                    })
+                };
+                let is_forward = |sign: anyhow::Result<&&str>| match *sign? {
+                    "+" => Ok(true),
+                    "-" => Ok(false),
+                    other => anyhow::bail!("Unknown sign {other:?} in L line {}", line_index + 1),
+                };
+                let overlap = columns
+                    .get(5)
+                    .map_or(Ok(0), |cigar| parse_overlap(cigar))
+                    .with_context(|| format!("Invalid overlap in L line {}", line_index + 1))?;
+
+                edges.push(BidirectedEdge {
+                    from: node(from_name)?,
+                    from_forward: is_forward(from_sign)?,
+                    to: node(to_name)?,
+                    to_forward: is_forward(to_sign)?,
+                    data: PlainGfaEdgeData::new(overlap),
+                });
+            }
+
+            "E" => {
+                let segment_length = |node: NodeIndex<IndexType>| match &segments[node].1 {
+                    Ok(sequence) => sequence.len(),
+                    Err(length) => *length,
+                };
+                let edge = parse_gfa2_edge(&columns, &node_name_to_node, segment_length)
+                    .with_context(|| format!("Invalid E line {}", line_index + 1))?;
+                let count = |(first, count): &mut (Option<_>, usize), description| {
+                    first.get_or_insert((line_index + 1, description));
+                    *count += 1;
+                };
+                match edge {
+                    Gfa2Edge::Dovetail(edge) => {
+                        edges.push(edge);
+                    }
+                    Gfa2Edge::Containment {
+                        contained,
+                        container,
+                    } => count(
+                        &mut containments,
+                        format!(
+                            "segment {} is contained in segment {}",
+                            segments[contained].0, segments[container].0,
+                        ),
+                    ),
+                    Gfa2Edge::Internal => count(&mut internal_alignments, String::new()),
+                }
+            }
+
+            // Fragments align external sequences to segments, which does not change the graph.
+            "F" => {}
+
+            "G" => {
+                gaps.0.get_or_insert(line_index + 1);
+                gaps.1 += 1;
+            }
+
+            other => {
+                if unsupported_line_types.insert(other.to_string()) {
+                    warn!("Ignoring unsupported GFA line type: {}", other);
+                }
+            }
+        }
+    }
+
+    if let (Some((line_number, description)), count) = containments {
+        warn!(
+            "Ignoring {count} containment edges, since a contained segment is not adjacent to its container. \
+             The first one is in line {line_number}, where {description}."
+        );
+    }
+    if let (Some((line_number, _)), count) = internal_alignments {
+        warn!(
+            "Ignoring {count} edges that are neither dovetail overlaps nor containments. \
+             The first one is in line {line_number}."
+        );
+    }
+    if let (Some(line_number), count) = gaps {
+        warn!(
+            "Ignoring {count} gaps, since they have no sequence that paths could traverse. \
+             The first one is in line {line_number}."
+        );
+    }
+
+    Ok(BidirectedAdjacencyArray::new(segment_data(segments), edges))
+}
+
+/// A GFA2 edge, classified by how its alignment relates the two segments.
+enum Gfa2Edge<IndexType> {
+    /// The alignment covers an end of each segment, which makes them adjacent.
+    Dovetail(BidirectedEdge<IndexType, PlainGfaEdgeData>),
+    /// The alignment covers the whole contained segment.
+    Containment {
+        contained: NodeIndex<IndexType>,
+        container: NodeIndex<IndexType>,
+    },
+    /// The alignment is inside of at least one of the segments.
+    Internal,
+}
+
+/// Parses and classifies a GFA2 edge (`E <eid> <sid1><sign> <sid2><sign> <beg1> <end1> <beg2> <end2> <alignment>`).
+///
+/// The positions are given on the forward strands of the segments, and a sign `-` means that the reverse complement is aligned.
+/// The overlap of a dovetail is the length of the aligned intervals, which must be the same on both segments.
+fn parse_gfa2_edge<IndexType: GraphIndexInteger>(
+    columns: &[&str],
+    node_name_to_node: &HashMap<String, NodeIndex<IndexType>>,
+    segment_length: impl Fn(NodeIndex<IndexType>) -> usize,
+) -> anyhow::Result<Gfa2Edge<IndexType>> {
+    if columns.len() < 9 {
+        anyhow::bail!("Expected at least 9 columns, but found {}", columns.len());
+    }
+
+    let reference = |reference: &str| -> anyhow::Result<(NodeIndex<IndexType>, bool)> {
+        let (name, forward) = if let Some(name) = reference.strip_suffix('+') {
+            (name, true)
+        } else if let Some(name) = reference.strip_suffix('-') {
+            (name, false)
+        } else {
+            anyhow::bail!("The segment reference {reference:?} does not end with a sign");
+        };
+        let node = node_name_to_node
+            .get(name)
+            .copied()
+            .with_context(|| format!("Unknown segment name {name:?}"))?;
+        Ok((node, forward))
+    };
+    let (node1, forward1) = reference(columns[2])?;
+    let (node2, forward2) = reference(columns[3])?;
+
+    let position = |column: &str, node: NodeIndex<IndexType>| -> anyhow::Result<usize> {
+        let (value, is_end) = match column.strip_suffix('$') {
+            Some(value) => (value, true),
+            None => (column, false),
+        };
+        let value = value
+            .parse()
+            .with_context(|| format!("Failed to parse position {column:?}"))?;
+        if (is_end && value != segment_length(node)) || value > segment_length(node) {
+            anyhow::bail!(
+                "The position {column:?} does not match the length {} of the segment",
+                segment_length(node),
+            );
+        }
+        Ok(value)
+    };
+    let [begin1, end1] = [columns[4], columns[5]].map(|column| position(column, node1));
+    let [begin2, end2] = [columns[6], columns[7]].map(|column| position(column, node2));
+    let (begin1, end1, begin2, end2) = (begin1?, end1?, begin2?, end2?);
+    if begin1 > end1 || begin2 > end2 {
+        anyhow::bail!("An aligned interval begins after its end");
+    }
+
+    let (is_prefix1, is_suffix1) = (begin1 == 0, end1 == segment_length(node1));
+    let (is_prefix2, is_suffix2) = (begin2 == 0, end2 == segment_length(node2));
+    if is_prefix1 && is_suffix1 {
+        return Ok(Gfa2Edge::Containment {
+            contained: node1,
+            container: node2,
+        });
+    }
+    if is_prefix2 && is_suffix2 {
+        return Ok(Gfa2Edge::Containment {
+            contained: node2,
+            container: node1,
+        });
+    }
+
+    // Aligning the reverse complements of both segments is the same as aligning the segments.
+    let ((from, from_forward), (to, to_forward)) = match (
+        forward1 == forward2,
+        is_prefix1,
+        is_suffix1,
+        is_prefix2,
+        is_suffix2,
+    ) {
+        (true, _, true, true, _) => ((node1, true), (node2, true)),
+        (true, true, _, _, true) => ((node2, true), (node1, true)),
+        (false, _, true, _, true) => ((node1, true), (node2, false)),
+        (false, true, _, true, _) => ((node2, false), (node1, true)),
+        _ => return Ok(Gfa2Edge::Internal),
+    };
+
+    if end1 - begin1 != end2 - begin2 {
+        anyhow::bail!(
+            "The overlap has length {} on the first segment, but {} on the second segment. \
+             Only overlaps of the same length on both segments are supported.",
+            end1 - begin1,
+            end2 - begin2,
+        );
+    }
+    let overlap = u16::try_from(end1 - begin1)
+        .with_context(|| format!("The overlap is longer than {}", u16::MAX))?;
+
+    Ok(Gfa2Edge::Dovetail(BidirectedEdge {
+        from,
+        from_forward,
+        to,
+        to_forward,
+        data: PlainGfaEdgeData::new(overlap),
+    }))
+}
+
+/// Returns the length given by the `LN:i:` tag among the optional fields of a segment, if any.
+fn segment_length_tag(tags: &[&str]) -> anyhow::Result<Option<usize>> {
+    tags.iter()
+        .find_map(|tag| tag.strip_prefix("LN:i:"))
+        .map(|length| {
+            length
+                .parse()
+                .with_context(|| format!("Failed to parse segment length {length:?}"))
+        })
+        .transpose()
+}
+
+/// Turns the parsed segments into node data, sharing one placeholder among all segments without a sequence.
+fn segment_data<IndexType: GraphIndexInteger>(
+    segments: TaggedVec<NodeIndex<IndexType>, (String, Result<String, usize>)>,
+) -> TaggedVec<NodeIndex<IndexType>, GfaSegmentData> {
+    let placeholder_length = segments
+        .iter_values()
+        .filter_map(|(_, sequence)| sequence.as_ref().err().copied())
+        .max()
+        .unwrap_or(0);
+    let placeholder: Arc<str> = "N".repeat(placeholder_length).into();
+
+    segments
+        .into_values_iter()
+        .map(|(name, sequence)| GfaSegmentData {
+            name,
+            sequence: match sequence {
+                Ok(sequence) => SegmentSequence::Given(sequence),
+                Err(length) => SegmentSequence::Unknown {
+                    placeholder: placeholder.clone(),
+                    length,
+                },
+            },
+        })
+        .collect()
+}
+
+/// Returns the length of the overlap described by the CIGAR string of a link.
+///
+/// The overlap must have the same length on both segments.
+/// A missing CIGAR string (`*`) is treated as no overlap.
+fn parse_overlap(cigar: &str) -> anyhow::Result<u16> {
+    if cigar == "*" {
+        return Ok(0);
+    }
+
+    let mut from_length = 0u64;
+    let mut to_length = 0u64;
+    let mut count = String::new();
+    for character in cigar.chars() {
+        if character.is_ascii_digit() {
+            count.push(character);
+            continue;
+        }
+
+        let count = std::mem::take(&mut count)
+            .parse::<u64>()
+            .with_context(|| format!("Missing count before {character:?} in CIGAR {cigar:?}"))?;
+        match character {
+            'M' | '=' | 'X' => {
+                from_length += count;
+                to_length += count;
+            }
+            'D' | 'N' => from_length += count,
+            'I' | 'S' => to_length += count,
+            'H' | 'P' => {}
+            other => anyhow::bail!("Unknown operation {other:?} in CIGAR {cigar:?}"),
+        }
+    }
+    if !count.is_empty() {
+        anyhow::bail!("Missing operation after the last count in CIGAR {cigar:?}");
+    }
+
+    if from_length != to_length {
+        anyhow::bail!(
+            "The overlap {cigar:?} has length {from_length} on the first segment, but {to_length} on the second segment. \
+             Only overlaps of the same length on both segments are supported."
+        );
+    }
+    u16::try_from(from_length)
+        .with_context(|| format!("The overlap {cigar:?} is longer than {}", u16::MAX))
+}
+
+/// Returns an error if the overlap of a link is longer than one of the segments it connects.
+fn check_overlaps<IndexType: GraphIndexInteger>(
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+) -> anyhow::Result<()> {
+    let node_length = |node: NodeIndex<IndexType>| graph.node_data(node).length();
+
+    for edge in graph.iter_edges() {
+        let edge = graph.edge(edge);
+        let overlap = usize::from(edge.data().overlap());
+        let (from, to) = (edge.from().into_bidirected(), edge.to().into_bidirected());
+        if let Some(node) = [from, to]
+            .into_iter()
+            .find(|&node| overlap > node_length(node))
+        {
+            anyhow::bail!(
+                "The link from {} to {} has overlap {overlap}, which is longer than segment {} of length {}",
+                graph.node_name(from),
+                graph.node_name(to),
+                graph.node_name(node),
+                node_length(node),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bidirected_adjacency_array::{
+        index::DirectedNodeIndex,
+        io::gfa1::{GfaEdgeData, GfaNodeData},
+    };
+
+    use super::{check_overlaps, parse_overlap, read_gfa, read_gfa_file};
+
+    const TINY1_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1.gfa");
+    const TINY1_LN_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1_ln.gfa");
+
+    #[test]
+    fn parse_overlap_lengths() {
+        assert_eq!(parse_overlap("*").unwrap(), 0);
+        assert_eq!(parse_overlap("0M").unwrap(), 0);
+        assert_eq!(parse_overlap("12M").unwrap(), 12);
+        assert_eq!(parse_overlap("3=1X2M").unwrap(), 6);
+        assert_eq!(parse_overlap("2M1I3M1D").unwrap(), 6);
+        assert_eq!(parse_overlap("2S4M2D").unwrap(), 6);
+        assert_eq!(parse_overlap("1H4M1N1I1P").unwrap(), 5);
+        assert_eq!(parse_overlap("65535M").unwrap(), u16::MAX);
+    }
+
+    #[test]
+    fn parse_overlap_errors() {
+        let error = |cigar| parse_overlap(cigar).unwrap_err().to_string();
+        assert_eq!(
+            error("4M1I"),
+            "The overlap \"4M1I\" has length 4 on the first segment, but 5 on the second segment. \
+             Only overlaps of the same length on both segments are supported."
+        );
+        assert_eq!(error("M"), "Missing count before 'M' in CIGAR \"M\"");
+        assert_eq!(
+            error("4M2"),
+            "Missing operation after the last count in CIGAR \"4M2\""
+        );
+        assert_eq!(error("4Q"), "Unknown operation 'Q' in CIGAR \"4Q\"");
+        assert_eq!(
+            error("65536M"),
+            "The overlap \"65536M\" is longer than 65535"
+        );
+    }
+
+    #[test]
+    fn links_have_overlaps() {
+        let graph = read_gfa::<u8>(
+            b"S\ta\tACGT\nS\tb\tGTA\nS\tc\tTT\n\
+              L\ta\t+\tb\t+\t2M\nL\tb\t+\tc\t-\t*\nL\tc\t+\ta\t+\n"
+                .as_slice(),
+        )
+        .unwrap();
+        let overlaps: Vec<_> = graph
+            .iter_edges()
+            .map(|edge| graph.edge(edge).data().overlap())
+            .collect();
+        assert_eq!(overlaps, [2, 0, 0]);
+        check_overlaps(&graph).unwrap();
+
+        let error = read_gfa::<u8>(b"S\ta\tA\nS\tb\tA\nL\ta\t+\tb\t+\t1M1D\n".as_slice())
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Invalid overlap in L line 3");
+    }
+
+    #[test]
+    fn overlaps_must_fit_into_the_segments() {
+        let graph =
+            read_gfa::<u8>(b"S\ta\tACGT\nS\tb\tGT\nL\ta\t+\tb\t+\t3M\n".as_slice()).unwrap();
+        assert_eq!(
+            check_overlaps(&graph).unwrap_err().to_string(),
+            "The link from a to b has overlap 3, which is longer than segment b of length 2"
+        );
+    }
+
+    #[test]
+    fn segment_lengths() {
+        let graph = read_gfa::<u8>(
+            b"S\ta\tACGT\nS\tb\t*\tLN:i:7\nS\tc\tGTA\tLN:i:3\nS\td\t5\t*\nS\te\t2\tAC\n".as_slice(),
+        )
+        .unwrap();
+        let lengths: Vec<_> = graph
+            .iter_nodes()
+            .map(|node| graph.node_data(node).length())
+            .collect();
+        assert_eq!(lengths, [4, 7, 3, 5, 2]);
+        let sequence_lengths: Vec<_> = graph
+            .iter_nodes()
+            .map(|node| graph.node_data(node).sequence().len())
+            .collect();
+        assert_eq!(sequence_lengths, lengths);
+        assert_eq!(
+            graph
+                .node_data(graph.iter_nodes().next().unwrap())
+                .sequence(),
+            "ACGT"
+        );
+    }
+
+    #[test]
+    fn segment_lengths_must_be_known_and_consistent() {
+        let error = |gfa: &[u8]| read_gfa::<u8>(gfa).err().unwrap().to_string();
+        assert_eq!(
+            error(b"S\ta\tACGT\nS\tb\t*\n"),
+            "Segment b in S line 2 has neither a sequence nor an LN tag, so its length is unknown"
+        );
+        assert_eq!(
+            error(b"S\ta\tACGT\tLN:i:5\n"),
+            "Segment a in S line 1 has a sequence of length 4, but its given length is 5"
+        );
+        assert_eq!(
+            error(b"S\ta\t3\tACGT\n"),
+            "Segment a in S line 1 has a sequence of length 4, but its given length is 3"
+        );
+        assert_eq!(error(b"S\ta\t*\tLN:i:x\n"), "Invalid LN tag in S line 1");
+    }
+
+    #[test]
+    fn tiny1_without_lengths_is_rejected() {
+        let error = read_gfa_file::<u8>(Path::new(TINY1_GFA)).err().unwrap();
+        assert!(
+            error.chain().any(|cause| cause.to_string()
+                == "Segment a in S line 2 has neither a sequence nor an LN tag, so its length is unknown"),
+            "{error:?}"
+        );
+
+        let graph = read_gfa_file::<u8>(Path::new(TINY1_LN_GFA)).unwrap();
+        assert_eq!(graph.node_count(), 11);
+        assert!(
+            graph
+                .iter_nodes()
+                .all(|node| graph.node_data(node).length() == 1)
+        );
+    }
+
+    /// Reads a GFA2 graph whose columns are separated by spaces,
+    /// and returns its adjacencies as the directed segments they connect and their overlap.
+    fn gfa2_adjacencies(gfa: &str) -> anyhow::Result<Vec<(String, String, u16)>> {
+        let graph = read_gfa::<u8>(gfa.replace(' ', "\t").as_bytes())?;
+        let directed_name = |node: DirectedNodeIndex<u8>| {
+            let name = graph.node_data(node.into_bidirected()).name();
+            format!("{name}{}", if node.is_forward() { '+' } else { '-' })
+        };
+        Ok(graph
+            .iter_edges()
+            .map(|edge| {
+                let edge = graph.edge(edge);
+                (
+                    directed_name(edge.from()),
+                    directed_name(edge.to()),
+                    edge.data().overlap(),
+                )
+            })
+            .collect())
+    }
+
+    const GFA2_SEGMENTS: &str = "H VN:Z:2.0\nS a 4 ACGT\nS b 4 *\nS c 2 TT\n";
+
+    #[test]
+    fn gfa2_dovetail_orientations() {
+        let adjacency = |edge: &str| {
+            let adjacencies = gfa2_adjacencies(&format!("{GFA2_SEGMENTS}E e {edge}\n")).unwrap();
+            assert_eq!(adjacencies.len(), 1, "{edge}");
+            adjacencies.into_iter().next().unwrap()
+        };
+        let expected = |from: &str, to: &str, overlap| (from.to_string(), to.to_string(), overlap);
+
+        // The suffix of the first segment aligns to the prefix of the second segment.
+        assert_eq!(adjacency("a+ b+ 2 4$ 0 2 2M"), expected("a+", "b+", 2));
+        // The prefix of the first segment aligns to the suffix of the second segment.
+        assert_eq!(adjacency("a+ b+ 0 1 3 4$ *"), expected("b+", "a+", 1));
+        // Two suffixes align if one of the segments is reverse complemented.
+        assert_eq!(adjacency("a+ b- 1 4$ 1 4$ 3M"), expected("a+", "b-", 3));
+        // Two prefixes align if one of the segments is reverse complemented.
+        assert_eq!(adjacency("a+ b- 0 2 0 2 *"), expected("b-", "a+", 2));
+        assert_eq!(adjacency("a- b+ 0 2 0 2 *"), expected("b-", "a+", 2));
+        // Reverse complementing both segments does not change the alignment.
+        assert_eq!(adjacency("a- b- 2 4$ 0 2 *"), expected("a+", "b+", 2));
+        // Overlaps may be empty.
+        assert_eq!(adjacency("a+ c+ 4$ 4$ 0 0 *"), expected("a+", "c+", 0));
+    }
+
+    #[test]
+    fn gfa2_containments_and_internal_alignments_are_ignored() {
+        let adjacencies = gfa2_adjacencies(&format!(
+            "{GFA2_SEGMENTS}\
+             E contained c+ a+ 0 2$ 1 3 2M\n\
+             E container a- c+ 1 3 0 2$ *\n\
+             E internal a+ b+ 1 3 1 3 2M\n\
+             E dovetail c+ a+ 1 2$ 0 1 1M\n"
+        ))
+        .unwrap();
+        assert_eq!(adjacencies, [("c+".to_string(), "a+".to_string(), 1)]);
+    }
+
+    #[test]
+    fn gfa2_edge_errors() {
+        let error = |edge: &str| {
+            let error = gfa2_adjacencies(&format!("{GFA2_SEGMENTS}E e {edge}\n"))
+                .err()
+                .unwrap();
+            assert_eq!(error.to_string(), "Invalid E line 5");
+            error.root_cause().to_string()
+        };
+        assert_eq!(
+            error("a+ b+ 2 3$ 0 1 *"),
+            "The position \"3$\" does not match the length 4 of the segment"
+        );
+        assert_eq!(
+            error("a+ b+ 2 4$ 0 5 *"),
+            "The position \"5\" does not match the length 4 of the segment"
+        );
+        assert_eq!(
+            error("a+ b+ 1 4$ 0 2 *"),
+            "The overlap has length 3 on the first segment, but 2 on the second segment. \
+             Only overlaps of the same length on both segments are supported."
+        );
+        assert_eq!(
+            error("a+ b+ 3 2 0 1 *"),
+            "An aligned interval begins after its end"
+        );
+        assert_eq!(
+            error("a b+ 2 4$ 0 2 *"),
+            "The segment reference \"a\" does not end with a sign"
+        );
+        assert_eq!(error("a+ d+ 2 4$ 0 2 *"), "Unknown segment name \"d\"");
+        assert_eq!(
+            error("a+ b+ 2 4$ 0 2"),
+            "Expected at least 9 columns, but found 8"
+        );
+    }
+}
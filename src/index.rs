@@ -1,17 +1,30 @@
-use std::{io::Write, path::PathBuf};
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use bidirected_adjacency_array::{
     graph::BidirectedAdjacencyArray,
     index::GraphIndexInteger,
-    io::gfa1::{PlainGfaEdgeData, PlainGfaNodeData},
+    io::gfa1::{GfaEdgeData, GfaNodeData},
 };
 use clap::Parser;
 use log::{LevelFilter, info};
-use spqr_shortest_path_index::spqr_decomposition_overlay::SPQRDecompositionOverlay;
-use spqr_tree::decomposition::SPQRDecomposition;
+use spqr_shortest_path_index::gfa_graph_extensions::GfaNodeDataExt;
+use spqr_tree::{decomposition::SPQRDecomposition, graph::StaticGraph};
 
-use crate::io_util::{read_optionally_compressed_file, write_optionally_compressed_file};
+use crate::{
+    gfa::read_gfa_file,
+    io_util::{
+        ensure_stdio_used_at_most_once, open_optionally_compressed_file,
+        write_optionally_compressed_file,
+    },
+    overlay::{KnownDistances, Overlay},
+    spqr_decomposition::{load_or_compute_spqr_decomposition, update_spqr_decomposition},
+    word_size::{fits_into_word_size, next_larger_word_size, select_word_size},
+};
 
 #[derive(Parser)]
 pub struct Cli {
@@ -23,61 +36,249 @@ pub struct Cli {
     graph_gfa_in: PathBuf,
 
     /// The SPQR decomposition in plain text format.
+    /// If it is not given, then it is computed from the graph.
     #[clap(long)]
-    spqr_in: PathBuf,
+    spqr_in: Option<PathBuf>,
 
     /// The output file for the index.
     #[clap(long)]
     index_out: PathBuf,
 
+    /// The number of threads to build the overlay with.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
     /// The integer size to use in all data structures.
     /// Supported values are 8, 16, 32, and 64.
-    /// If the program crashes during reading the graph, try using a larger word size.
-    #[clap(long, default_value = "32")]
-    word_size: u8,
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    #[clap(long)]
+    word_size: Option<u8>,
+
+    /// An index of an older version of the graph to update instead of building the index from scratch.
+    /// The SPQR decomposition and the overlay of each connected component that is unchanged in the old graph are copied,
+    /// and only the changed components are recomputed.
+    /// The word size of the old index is kept.
+    #[clap(long, requires = "old_graph_gfa_in", conflicts_with_all = ["spqr_in", "word_size"])]
+    update: Option<PathBuf>,
+
+    /// The GFA file containing the old graph of the index given with `--update`.
+    #[clap(long, requires = "update")]
+    old_graph_gfa_in: Option<PathBuf>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
 }
 
 pub fn run(cli: Cli) -> anyhow::Result<()> {
-    match cli.word_size {
-        8 => run_with_word_size::<u8>(cli),
-        16 => run_with_word_size::<u16>(cli),
-        32 => run_with_word_size::<u32>(cli),
-        64 => run_with_word_size::<u64>(cli),
-        _ => anyhow::bail!(
-            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
-            cli.word_size
-        ),
+    ensure_stdio_used_at_most_once(
+        [
+            Some(cli.graph_gfa_in.as_path()),
+            cli.spqr_in.as_deref(),
+            cli.update.as_deref(),
+            cli.old_graph_gfa_in.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+        [cli.index_out.as_path()],
+    )?;
+
+    if let Some(update) = &cli.update {
+        let (old_index_header, old_index_file_reader) = read_index_header(update)?;
+        let word_size = select_word_size(&cli.graph_gfa_in, Some(old_index_header.word_size))
+            .with_context(|| {
+                format!(
+                    "The graph does not fit into the word size of the old index {:?}, build a new index instead",
+                    update
+                )
+            })?;
+        return match word_size {
+            8 => update_with_word_size::<u8>(cli, old_index_header, old_index_file_reader),
+            16 => update_with_word_size::<u16>(cli, old_index_header, old_index_file_reader),
+            32 => update_with_word_size::<u32>(cli, old_index_header, old_index_file_reader),
+            64 => update_with_word_size::<u64>(cli, old_index_header, old_index_file_reader),
+            word_size => unreachable!("Unsupported word size: {word_size}"),
+        };
+    }
+
+    let mut word_size = select_word_size(&cli.graph_gfa_in, cli.word_size)?;
+    loop {
+        let result = match word_size {
+            8 => run_with_word_size::<u8>(&cli),
+            16 => run_with_word_size::<u16>(&cli),
+            32 => run_with_word_size::<u32>(&cli),
+            64 => run_with_word_size::<u64>(&cli),
+            word_size => unreachable!("Unsupported word size: {word_size}"),
+        };
+
+        // The size of the overlay is only known after decomposing the graph,
+        // so an automatically selected word size may turn out to be too small.
+        match result {
+            Err(error) if cli.word_size.is_none() && error.is::<OverlayTooLarge>() => {
+                let Some(larger_word_size) = next_larger_word_size(word_size) else {
+                    return Err(error);
+                };
+                info!("{error}, retrying with word size {larger_word_size}");
+                word_size = larger_word_size;
+            }
+            result => return result,
+        }
     }
 }
 
-fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<()> {
+/// The overlay may have too many edges for the word size.
+#[derive(Debug)]
+struct OverlayTooLarge {
+    max_overlay_edge_count: u64,
+    word_size: u8,
+}
+
+impl std::fmt::Display for OverlayTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The overlay may have up to {} edges, which do not fit into word size {}",
+            self.max_overlay_edge_count, self.word_size,
+        )
+    }
+}
+
+impl std::error::Error for OverlayTooLarge {}
+
+fn run_with_word_size<IndexType: GraphIndexInteger + Send + Sync>(cli: &Cli) -> anyhow::Result<()> {
     info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
-    let graph = read_optionally_compressed_file(&cli.graph_gfa_in, |reader| {
-        BidirectedAdjacencyArray::<IndexType, PlainGfaNodeData, PlainGfaEdgeData>::read_gfa1(reader)
-            .with_context(|| format!("Failed to parse GFA file {:?}", cli.graph_gfa_in))
-    })
-    .with_context(|| format!("Failed to read GFA file: {:?}", cli.graph_gfa_in))?;
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
     info!(
         "Graph has {} nodes and {} edges",
         graph.node_count(),
         graph.edge_count(),
     );
 
-    info!("Reading SPQR decomposition from file {:?}", cli.spqr_in);
-    let spqr_decomposition = read_optionally_compressed_file(&cli.spqr_in, |reader| {
-        SPQRDecomposition::read_plain_spqr(&graph, reader)
-            .with_context(|| format!("Failed to parse SPQR decomposition file {:?}", cli.spqr_in))
-    })
-    .with_context(|| format!("Failed to read SPQR file: {:?}", cli.spqr_in))?;
+    let spqr_decomposition = load_or_compute_spqr_decomposition(&graph, cli.spqr_in.as_deref())?;
+
+    check_overlay_size(&spqr_decomposition)?;
 
     info!("Building overlay");
-    let overlay = SPQRDecompositionOverlay::new(&graph, &spqr_decomposition);
+    let overlay = Overlay::build(&graph, &spqr_decomposition, cli.threads, None);
+
+    write_index(cli, &graph, &spqr_decomposition, &overlay)
+}
+
+/// Returns [`OverlayTooLarge`] if the overlay of the SPQR decomposition may not fit into the word size.
+fn check_overlay_size<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    spqr_decomposition: &SPQRDecomposition<BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+) -> anyhow::Result<()> {
+    let word_size = word_size::<IndexType>();
+    let max_overlay_edge_count = max_overlay_edge_count(spqr_decomposition);
+    // Each bidirected edge is stored as two directed ones.
+    if !fits_into_word_size(2 * max_overlay_edge_count, word_size) {
+        return Err(OverlayTooLarge {
+            max_overlay_edge_count,
+            word_size,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns the word size in bits of the index type.
+fn word_size<IndexType>() -> u8 {
+    u8::try_from(std::mem::size_of::<IndexType>() * 8).unwrap()
+}
+
+fn update_with_word_size<IndexType: GraphIndexInteger + Send + Sync>(
+    cli: Cli,
+    old_index_header: IndexHeader,
+    mut old_index_file_reader: impl BufRead,
+) -> anyhow::Result<()> {
+    let old_index_in = cli.update.as_deref().unwrap();
+    let old_graph_gfa_in = cli.old_graph_gfa_in.as_deref().unwrap();
+
+    info!("Reading old graph from GFA file {:?}", old_graph_gfa_in);
+    let old_graph = read_gfa_file::<IndexType>(old_graph_gfa_in)?;
+    old_index_header.check_graph(&old_graph, old_index_in)?;
+
+    info!("Reading old index from file {:?}", old_index_in);
+    let old_spqr_decomposition =
+        SPQRDecomposition::read_binary(&old_graph, &mut old_index_file_reader).with_context(
+            || {
+                format!(
+                    "Failed to read SPQR decomposition from index file: {:?}",
+                    old_index_in
+                )
+            },
+        )?;
+    old_index_header.check_spqr_decomposition(&old_spqr_decomposition, old_index_in)?;
+    let old_overlay =
+        Overlay::read_binary(&old_graph, &old_spqr_decomposition, old_index_file_reader)
+            .with_context(|| format!("Failed to read index file: {:?}", old_index_in))?;
+
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    info!("Updating SPQR decomposition");
+    let (spqr_decomposition, old_to_new_nodes) =
+        update_spqr_decomposition(&graph, &old_spqr_decomposition)?;
+    check_overlay_size(&spqr_decomposition).with_context(|| {
+        format!(
+            "The updated graph does not fit into the word size of the old index {:?}, build a new index instead",
+            old_index_in
+        )
+    })?;
+
+    info!("Updating overlay");
+    let known_distances = KnownDistances::new(&old_overlay, &old_to_new_nodes, graph.node_count());
+    let overlay = Overlay::build(
+        &graph,
+        &spqr_decomposition,
+        cli.threads,
+        Some(&known_distances),
+    );
+
+    write_index(&cli, &graph, &spqr_decomposition, &overlay)
+}
+
+/// Writes the header, the SPQR decomposition and the overlay to the index file.
+fn write_index<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    cli: &Cli,
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    spqr_decomposition: &SPQRDecomposition<BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    overlay: &Overlay<IndexType, NodeData, EdgeData>,
+) -> anyhow::Result<()> {
+    info!("Computing graph and SPQR decomposition fingerprints");
+    let index_header = IndexHeader {
+        word_size: word_size::<IndexType>(),
+        node_count: graph.node_count() as u64,
+        edge_count: graph.edge_count() as u64,
+        graph_hash: graph_hash(graph),
+        spqr_decomposition_hash: spqr_decomposition_hash(spqr_decomposition),
+    };
 
     info!("Writing index to file {:?}", cli.index_out);
-    write_optionally_compressed_file(&cli.index_out, |writer| {
-        writer
-            .write_all(&[u8::try_from(std::mem::size_of::<IndexType>() * 8).unwrap()])
+    write_optionally_compressed_file(&cli.index_out, cli.compression_level, |writer| {
+        index_header
+            .write(&mut *writer)
             .with_context(|| format!("Failed to write index header to file {:?}", cli.index_out))?;
+        spqr_decomposition
+            .write_binary(&mut *writer)
+            .with_context(|| {
+                format!(
+                    "I/O error while writing SPQR decomposition to index file: {:?}",
+                    cli.index_out
+                )
+            })?;
         overlay
             .write_binary(writer)
             .with_context(|| format!("I/O error while writing index to file: {:?}", cli.index_out))
@@ -87,3 +288,357 @@ fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<
     info!("Indexing completed successfully");
     Ok(())
 }
+
+/// Returns an upper bound for the number of edges of the overlay of the SPQR decomposition.
+///
+/// The overlay can have many more edges than the graph, since it connects all pairs of nodes
+/// that are incident to virtual edges of the same SPQR node, and all pairs of cut nodes of the same block.
+fn max_overlay_edge_count<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    spqr_decomposition: &SPQRDecomposition<BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+) -> u64 {
+    // Each pair of nodes and each single node (for self-loops) gets at most four edges.
+    let max_edge_count = |node_count: u64| 4 * (node_count * (node_count + 1) / 2);
+
+    let mut result = 0;
+    for (block_index, block) in spqr_decomposition.iter_blocks() {
+        result += max_edge_count(block.iter_cut_nodes().count() as u64);
+        for (_, spqr_node) in spqr_decomposition.iter_spqr_nodes_in_block(block_index) {
+            let nodes: HashSet<_> = spqr_node
+                .iter_incident_spqr_edges()
+                .flat_map(|spqr_edge_index| {
+                    let (a, b) = spqr_decomposition.spqr_edge(spqr_edge_index).virtual_edge();
+                    [a, b]
+                })
+                .collect();
+            result += max_edge_count(nodes.len() as u64);
+        }
+    }
+    result
+}
+
+/// The magic bytes at the start of every index file.
+const INDEX_MAGIC: &[u8; 8] = b"BIOPATHI";
+
+/// The version of the index file format.
+/// Must be incremented whenever the layout or the meaning of the index file changes.
+/// Version 2 stores overlay distances that subtract the overlaps of the links.
+/// Version 3 stores the two levels of the overlay as separate graphs.
+pub(crate) const INDEX_FORMAT_VERSION: u32 = 3;
+
+/// The header of an index file.
+///
+/// After the header, the index contains the SPQR decomposition followed by the overlay, both in binary format.
+/// All integers are stored in little endian.
+pub(crate) struct IndexHeader {
+    pub(crate) word_size: u8,
+    pub(crate) node_count: u64,
+    pub(crate) edge_count: u64,
+    pub(crate) graph_hash: u64,
+    pub(crate) spqr_decomposition_hash: u64,
+}
+
+impl IndexHeader {
+    pub(crate) fn write(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(INDEX_MAGIC)?;
+        writer.write_all(&INDEX_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&[self.word_size])?;
+        writer.write_all(&self.node_count.to_le_bytes())?;
+        writer.write_all(&self.edge_count.to_le_bytes())?;
+        writer.write_all(&self.graph_hash.to_le_bytes())?;
+        writer.write_all(&self.spqr_decomposition_hash.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn read(mut reader: impl Read, index_in: &Path) -> anyhow::Result<Self> {
+        let mut magic = [0u8; INDEX_MAGIC.len()];
+        let magic_result = reader.read_exact(&mut magic);
+        if magic_result.is_err() || &magic != INDEX_MAGIC {
+            anyhow::bail!(
+                "File {:?} is not a biopath index file (missing magic bytes)",
+                index_in
+            );
+        }
+
+        let mut version_bytes = [0u8; 4];
+        reader.read_exact(&mut version_bytes)?;
+        let version = u32::from_le_bytes(version_bytes);
+        if version != INDEX_FORMAT_VERSION {
+            anyhow::bail!(
+                "Index file {:?} has format version {}, but this version of biopath only supports version {}. \
+                 Please rebuild the index.",
+                index_in,
+                version,
+                INDEX_FORMAT_VERSION
+            );
+        }
+
+        let mut word_size = [0u8; 1];
+        reader.read_exact(&mut word_size)?;
+        let mut read_u64 = || -> std::io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        Ok(Self {
+            word_size: word_size[0],
+            node_count: read_u64()?,
+            edge_count: read_u64()?,
+            graph_hash: read_u64()?,
+            spqr_decomposition_hash: read_u64()?,
+        })
+    }
+
+    /// Ensures that the index was built from the given graph.
+    pub(crate) fn check_graph<
+        IndexType: GraphIndexInteger,
+        NodeData: GfaNodeData,
+        EdgeData: GfaEdgeData,
+    >(
+        &self,
+        graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        index_in: &Path,
+    ) -> anyhow::Result<()> {
+        if self.node_count != graph.node_count() as u64
+            || self.edge_count != graph.edge_count() as u64
+        {
+            anyhow::bail!(
+                "Index file {:?} was built from a different graph: \
+                 the index has {} nodes and {} edges, but the graph has {} nodes and {} edges",
+                index_in,
+                self.node_count,
+                self.edge_count,
+                graph.node_count(),
+                graph.edge_count(),
+            );
+        }
+
+        if self.graph_hash != graph_hash(graph) {
+            anyhow::bail!(
+                "Index file {:?} was built from a different graph: \
+                 the node names, sequence lengths or edges do not match",
+                index_in
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that the SPQR decomposition read from the index is the one that the index was built with.
+    pub(crate) fn check_spqr_decomposition<Graph: StaticGraph>(
+        &self,
+        spqr_decomposition: &SPQRDecomposition<'_, Graph>,
+        index_in: &Path,
+    ) -> anyhow::Result<()> {
+        if self.spqr_decomposition_hash != spqr_decomposition_hash(spqr_decomposition) {
+            anyhow::bail!(
+                "The SPQR decomposition in index file {:?} does not match its fingerprint, the file may be corrupted",
+                index_in
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the index file and reads its header.
+/// Returns the header and a reader positioned after the header.
+pub(crate) fn read_index_header(
+    index_in: &Path,
+) -> anyhow::Result<(IndexHeader, BufReader<Box<dyn Read>>)> {
+    let mut index_file_reader = BufReader::new(
+        open_optionally_compressed_file(index_in)
+            .with_context(|| format!("Failed to open index file {:?}", index_in))?,
+    );
+    let index_header = IndexHeader::read(&mut index_file_reader, index_in)
+        .with_context(|| format!("Failed to read index header from file {:?}", index_in))?;
+
+    info!(
+        "Discovered word size {} bits from index file header",
+        index_header.word_size
+    );
+
+    Ok((index_header, index_file_reader))
+}
+
+/// Computes a fingerprint of the graph from its node names, sequence lengths, edges and their overlaps.
+///
+/// Node and edge indices depend on the order of the lines in the GFA file, so reordering the file changes the fingerprint.
+fn graph_hash<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+) -> u64 {
+    let mut hasher = Fnv1aHasher::default();
+    for node in graph.iter_nodes() {
+        let node_data = graph.node_data(node);
+        hasher.write_bytes(node_data.name().as_bytes());
+        hasher.write_u64(GfaNodeDataExt::<IndexType>::len(node_data).into_usize() as u64);
+    }
+    for edge in graph.iter_edges() {
+        let edge = graph.edge(edge);
+        hasher.write_u64(edge.from().into_usize() as u64);
+        hasher.write_u64(edge.to().into_usize() as u64);
+        hasher.write_u64(edge.data().overlap().into());
+    }
+    hasher.finish()
+}
+
+/// Computes a fingerprint of the binary representation of the SPQR decomposition.
+fn spqr_decomposition_hash<Graph: StaticGraph>(
+    spqr_decomposition: &SPQRDecomposition<'_, Graph>,
+) -> u64 {
+    let mut hasher = Fnv1aHasher::default();
+    spqr_decomposition
+        .write_binary(&mut hasher)
+        .expect("writing to a hasher never fails");
+    hasher.finish()
+}
+
+/// The 64-bit FNV-1a hash function.
+///
+/// Unlike the hasher of the standard library, its output is guaranteed to be stable, so it can be stored in files.
+struct Fnv1aHasher {
+    state: u64,
+}
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self {
+            state: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+}
+
+impl Fnv1aHasher {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Write for Fnv1aHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfa::read_gfa;
+
+    fn header() -> IndexHeader {
+        IndexHeader {
+            word_size: 16,
+            node_count: 3,
+            edge_count: 2,
+            graph_hash: 0x0123_4567_89ab_cdef,
+            spqr_decomposition_hash: 42,
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 + 4 + 1 + 4 * 8);
+        assert_eq!(&bytes[..8], INDEX_MAGIC);
+
+        let read_header = IndexHeader::read(bytes.as_slice(), Path::new("test.index")).unwrap();
+        assert_eq!(read_header.word_size, 16);
+        assert_eq!(read_header.node_count, 3);
+        assert_eq!(read_header.edge_count, 2);
+        assert_eq!(read_header.graph_hash, 0x0123_4567_89ab_cdef);
+        assert_eq!(read_header.spqr_decomposition_hash, 42);
+    }
+
+    #[test]
+    fn header_with_wrong_magic_or_version_is_rejected() {
+        let error = IndexHeader::read(b"H\tVN:Z:1.0\n".as_slice(), Path::new("graph.gfa"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "File \"graph.gfa\" is not a biopath index file (missing magic bytes)",
+        );
+
+        let mut bytes = Vec::new();
+        header().write(&mut bytes).unwrap();
+        bytes[8..12].copy_from_slice(&1u32.to_le_bytes());
+        let error = IndexHeader::read(bytes.as_slice(), Path::new("old.index"))
+            .err()
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Index file \"old.index\" has format version 1, but")
+        );
+    }
+
+    #[test]
+    fn check_graph_detects_a_different_graph() {
+        let graph = read_gfa::<u32>(b"S\ta\tAC\nS\tb\tA\nL\ta\t+\tb\t-\t1M\n".as_slice()).unwrap();
+        let index_header = IndexHeader {
+            word_size: 32,
+            node_count: 2,
+            edge_count: 1,
+            graph_hash: graph_hash(&graph),
+            spqr_decomposition_hash: 0,
+        };
+        index_header
+            .check_graph(&graph, Path::new("test.index"))
+            .unwrap();
+
+        for other_gfa in [
+            "S\ta\tAC\nS\tc\tA\nL\ta\t+\tc\t-\t1M\n",
+            "S\ta\tAC\nS\tb\tAA\nL\ta\t+\tb\t-\t1M\n",
+            "S\ta\tAC\nS\tb\tA\nL\ta\t+\tb\t+\t1M\n",
+            "S\ta\tAC\nS\tb\tA\nL\ta\t+\tb\t-\t0M\n",
+        ] {
+            let other_graph = read_gfa::<u32>(other_gfa.as_bytes()).unwrap();
+            assert!(
+                index_header
+                    .check_graph(&other_graph, Path::new("test.index"))
+                    .is_err(),
+                "{other_gfa:?}",
+            );
+        }
+
+        let larger_graph = read_gfa::<u32>(b"S\ta\tAC\nS\tb\tA\nS\tc\tA\n".as_slice()).unwrap();
+        let error = index_header
+            .check_graph(&larger_graph, Path::new("test.index"))
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("the graph has 3 nodes and 0 edges")
+        );
+    }
+
+    #[test]
+    fn fnv1a_hash_is_stable() {
+        let hasher = Fnv1aHasher::default();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        let mut hasher = Fnv1aHasher::default();
+        hasher.write_bytes(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+}
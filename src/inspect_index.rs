@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray,
+    index::{DirectedNodeIndex, GraphIndexInteger},
+    io::gfa1::PlainGfaEdgeData,
+};
+use clap::Parser;
+use log::{LevelFilter, info};
+use spqr_shortest_path_index::path::GfaPathLength;
+use spqr_tree::{decomposition::SPQRDecomposition, graph::StaticGraph};
+
+use crate::{
+    gfa::{GfaSegmentData, read_gfa_file},
+    index::{INDEX_FORMAT_VERSION, IndexHeader, read_index_header},
+    io_util::{ensure_stdio_used_at_most_once, summary_writer, write_optionally_compressed_file},
+    overlay::Overlay,
+};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph that the index was built from.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The index file to inspect.
+    #[clap(long)]
+    index_in: PathBuf,
+
+    /// The name of a block whose stored distances should be dumped, e.g. `B0`.
+    /// Blocks are named like in the plain text SPQR decomposition.
+    #[clap(long, requires = "block_distances_tsv_out")]
+    block: Option<String>,
+
+    /// The output file for the distances stored for the block given with `--block`.
+    /// Each line contains the overlay level, the oriented source and target nodes and the distance, separated by tabs.
+    /// The distance is measured from the start of the source node to the end of the target node.
+    /// Distances from a cut node to itself are stored only once, but reported for each block of the cut node.
+    #[clap(long, requires = "block")]
+    block_distances_tsv_out: Option<PathBuf>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
+}
+
+/// The level of the overlay that an overlay edge belongs to.
+#[derive(Clone, Copy)]
+enum OverlayEdgeLevel {
+    /// Edges between the nodes incident to virtual edges of the same SPQR node.
+    SPQRTree,
+    /// Edges between the cut nodes of the same block.
+    BlockCutTree,
+}
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once(
+        [cli.graph_gfa_in.as_path(), cli.index_in.as_path()],
+        cli.block_distances_tsv_out.as_deref(),
+    )?;
+
+    let (index_header, index_file_reader) = read_index_header(&cli.index_in)?;
+
+    match index_header.word_size {
+        8 => run_with_word_size::<u8>(cli, index_header, index_file_reader),
+        16 => run_with_word_size::<u16>(cli, index_header, index_file_reader),
+        32 => run_with_word_size::<u32>(cli, index_header, index_file_reader),
+        64 => run_with_word_size::<u64>(cli, index_header, index_file_reader),
+        _ => anyhow::bail!(
+            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
+            index_header.word_size
+        ),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger>(
+    cli: Cli,
+    index_header: IndexHeader,
+    mut index_file_reader: impl BufRead,
+) -> anyhow::Result<()> {
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    index_header.check_graph(&graph, &cli.index_in)?;
+
+    info!("Reading index from file {:?}", cli.index_in);
+    let spqr_decomposition = SPQRDecomposition::read_binary(&graph, &mut index_file_reader)
+        .with_context(|| {
+            format!(
+                "Failed to read SPQR decomposition from index file: {:?}",
+                cli.index_in
+            )
+        })?;
+    index_header.check_spqr_decomposition(&spqr_decomposition, &cli.index_in)?;
+    let overlay = Overlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
+        .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
+
+    let block_to_be_dumped = cli
+        .block
+        .as_ref()
+        .map(|block_name| {
+            spqr_decomposition
+                .iter_blocks()
+                .map(|(block_index, _)| block_index)
+                .find(|block_index| format!("B{block_index}") == *block_name)
+                .with_context(|| format!("Block {block_name} does not exist in the index"))
+        })
+        .transpose()?;
+
+    info!("Computing the sizes of the index parts");
+    let header_size = byte_size(|writer| index_header.write(writer));
+    let spqr_decomposition_size = byte_size(|writer| spqr_decomposition.write_binary(writer));
+    let overlay_size = byte_size(|writer| overlay.write_binary(writer));
+
+    info!("Counting the stored distances");
+    let mut spqr_tree_distance_count = 0;
+    let mut block_cut_tree_distance_count = 0;
+    let mut block_distance_counts = HashMap::new();
+    let mut dumped_distances = Vec::new();
+    for node in overlay.iter_nodes() {
+        for from in [node.into_directed_forward(), node.into_directed_reverse()] {
+            let edges = overlay
+                .iter_outgoing_spqr_tree_edges(from)
+                .map(|(to, length)| (OverlayEdgeLevel::SPQRTree, to, length))
+                .chain(
+                    overlay
+                        .iter_outgoing_block_cut_tree_edges(from)
+                        .map(|(to, length)| (OverlayEdgeLevel::BlockCutTree, to, length)),
+                );
+            for (level, to, length) in edges {
+                match level {
+                    OverlayEdgeLevel::SPQRTree => spqr_tree_distance_count += 1,
+                    OverlayEdgeLevel::BlockCutTree => block_cut_tree_distance_count += 1,
+                }
+
+                // Both endpoints of an overlay edge are in the same block.
+                // Only distances from a cut node to itself belong to several blocks.
+                for block_index in spqr_decomposition
+                    .node_block_indices(from.into_bidirected())
+                    .filter(|&block_index| {
+                        spqr_decomposition
+                            .node_block_indices(to.into_bidirected())
+                            .any(|other_block_index| other_block_index == block_index)
+                    })
+                {
+                    *block_distance_counts.entry(block_index).or_insert(0usize) += 1;
+                    if Some(block_index) == block_to_be_dumped {
+                        dumped_distances.push((level, from, to, length));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut summary = summary_writer(cli.block_distances_tsv_out.as_deref());
+    writeln!(summary, "format_version = {INDEX_FORMAT_VERSION}")?;
+    writeln!(summary, "word_size = {}", index_header.word_size)?;
+    writeln!(summary, "node_count = {}", index_header.node_count)?;
+    writeln!(summary, "edge_count = {}", index_header.edge_count)?;
+    writeln!(summary, "graph_hash = {:016x}", index_header.graph_hash)?;
+    writeln!(
+        summary,
+        "spqr_decomposition_hash = {:016x}",
+        index_header.spqr_decomposition_hash
+    )?;
+    writeln!(summary)?;
+    writeln!(
+        summary,
+        "component_count = {}",
+        spqr_decomposition.component_count()
+    )?;
+    writeln!(
+        summary,
+        "block_count = {}",
+        spqr_decomposition.block_count()
+    )?;
+    writeln!(
+        summary,
+        "spqr_node_count = {}",
+        spqr_decomposition.spqr_node_count()
+    )?;
+    writeln!(summary, "overlay_node_count = {}", overlay.node_count())?;
+    writeln!(summary, "overlay_edge_count = {}", overlay.edge_count())?;
+    writeln!(
+        summary,
+        "distance_count = {}",
+        spqr_tree_distance_count + block_cut_tree_distance_count
+    )?;
+    writeln!(
+        summary,
+        "spqr_tree_distance_count = {spqr_tree_distance_count}"
+    )?;
+    writeln!(
+        summary,
+        "block_cut_tree_distance_count = {block_cut_tree_distance_count}"
+    )?;
+    writeln!(summary)?;
+    writeln!(summary, "header_bytes = {header_size}")?;
+    writeln!(
+        summary,
+        "spqr_decomposition_bytes = {spqr_decomposition_size}"
+    )?;
+    writeln!(summary, "overlay_bytes = {overlay_size}")?;
+    writeln!(
+        summary,
+        "total_bytes = {}",
+        header_size + spqr_decomposition_size + overlay_size
+    )?;
+    writeln!(summary)?;
+    for (component_index, component) in spqr_decomposition.iter_components() {
+        writeln!(
+            summary,
+            "component G{component_index}: {} nodes, {} blocks",
+            component.node_count(),
+            component.block_count(),
+        )?;
+        for (block_index, block) in spqr_decomposition.iter_blocks_in_component(component_index) {
+            writeln!(
+                summary,
+                "  block B{block_index}: {} nodes, {} cut nodes, {} SPQR nodes, {} distances",
+                block.node_count(),
+                block.iter_cut_nodes().count(),
+                block.spqr_node_count(),
+                block_distance_counts.get(&block_index).unwrap_or(&0),
+            )?;
+        }
+    }
+
+    if let Some(block_distances_tsv_out) = &cli.block_distances_tsv_out {
+        info!(
+            "Writing {} distances of block {} to file {:?}",
+            dumped_distances.len(),
+            cli.block.as_ref().unwrap(),
+            block_distances_tsv_out
+        );
+        write_optionally_compressed_file(
+            block_distances_tsv_out,
+            cli.compression_level,
+            |writer| {
+                for (level, from, to, length) in dumped_distances {
+                    write_distance(writer, &graph, level, from, to, length)?;
+                }
+                Ok(())
+            },
+        )
+        .with_context(|| {
+            format!(
+                "Failed to write block distances to file {:?}",
+                block_distances_tsv_out
+            )
+        })?;
+    }
+
+    info!("Finished");
+    Ok(())
+}
+
+fn write_distance<IndexType: GraphIndexInteger>(
+    writer: &mut (impl Write + ?Sized),
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    level: OverlayEdgeLevel,
+    from: DirectedNodeIndex<IndexType>,
+    to: DirectedNodeIndex<IndexType>,
+    length: GfaPathLength<IndexType>,
+) -> std::io::Result<()> {
+    let format_node = |node: DirectedNodeIndex<IndexType>| {
+        format!(
+            "{}{}",
+            graph.node_name(node.into_bidirected()),
+            if node.is_forward() { "+" } else { "-" },
+        )
+    };
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}",
+        match level {
+            OverlayEdgeLevel::SPQRTree => "spqr_tree",
+            OverlayEdgeLevel::BlockCutTree => "block_cut_tree",
+        },
+        format_node(from),
+        format_node(to),
+        length,
+    )
+}
+
+/// Returns the number of bytes written by the given function.
+fn byte_size(write: impl FnOnce(&mut ByteCounter) -> std::io::Result<()>) -> u64 {
+    let mut byte_counter = ByteCounter(0);
+    // Counting bytes cannot fail.
+    write(&mut byte_counter).unwrap();
+    byte_counter.0
+}
+
+/// A writer that discards the written bytes and only counts them.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
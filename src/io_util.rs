@@ -1,12 +1,186 @@
 use std::{
     fs::File,
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufRead, BufReader, BufWriter, Cursor, IntoInnerError, Read, Stdout, Write},
     path::Path,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::Context;
 
-/// Opens the file and pipes it through a decompressor if the file extension indicates that it is compressed.
+/// The path that stands for stdin when reading and for stdout when writing.
+const STDIO_PATH: &str = "-";
+
+/// Returns true if the path stands for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+/// How stdin has been read so far.
+enum StdinState {
+    Unread,
+    /// Stdin was handed out as a stream, so it cannot be read again.
+    Streamed,
+    /// Stdin was read into memory, so it can be read any number of times.
+    Buffered(Arc<[u8]>),
+}
+
+static STDIN_STATE: Mutex<StdinState> = Mutex::new(StdinState::Unread);
+
+/// Whether stdout has been handed out as a writer.
+static STDOUT_CREATED: Mutex<bool> = Mutex::new(false);
+
+/// Reads stdin into memory if the path stands for it, such that it can be read more than once.
+///
+/// This is required for files that are read multiple times, like the GFA file,
+/// which is scanned to select the word size before it is loaded.
+pub fn make_rereadable(path: &Path) -> anyhow::Result<()> {
+    if !is_stdio(path) {
+        return Ok(());
+    }
+
+    let mut stdin_state = STDIN_STATE.lock().unwrap();
+    match *stdin_state {
+        StdinState::Unread => {
+            let mut buffer = Vec::new();
+            std::io::stdin()
+                .lock()
+                .read_to_end(&mut buffer)
+                .context("Failed to read stdin")?;
+            *stdin_state = StdinState::Buffered(buffer.into());
+            Ok(())
+        }
+        StdinState::Streamed => anyhow::bail!("Stdin was already read"),
+        StdinState::Buffered(_) => Ok(()),
+    }
+}
+
+/// Returns an error if stdin or stdout is given for more than one of the input or output files respectively.
+pub fn ensure_stdio_used_at_most_once<'path>(
+    inputs: impl IntoIterator<Item = &'path Path>,
+    outputs: impl IntoIterator<Item = &'path Path>,
+) -> anyhow::Result<()> {
+    if inputs.into_iter().filter(|path| is_stdio(path)).count() > 1 {
+        anyhow::bail!("Stdin ({STDIO_PATH:?}) can be given for at most one input file");
+    }
+    if outputs.into_iter().filter(|path| is_stdio(path)).count() > 1 {
+        anyhow::bail!("Stdout ({STDIO_PATH:?}) can be given for at most one output file");
+    }
+    Ok(())
+}
+
+/// Returns stdout for printing a summary, or stderr if one of the output files is written to stdout,
+/// such that the summary does not mix with the output file.
+pub fn summary_writer<'path>(outputs: impl IntoIterator<Item = &'path Path>) -> Box<dyn Write> {
+    if outputs.into_iter().any(is_stdio) {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    }
+}
+
+/// Opens stdin as a buffered reader.
+fn open_stdin() -> anyhow::Result<Box<dyn BufRead>> {
+    let mut stdin_state = STDIN_STATE.lock().unwrap();
+    match &*stdin_state {
+        StdinState::Unread => {
+            *stdin_state = StdinState::Streamed;
+            Ok(Box::new(std::io::stdin().lock()))
+        }
+        StdinState::Streamed => anyhow::bail!("Stdin was already read and cannot be read again"),
+        StdinState::Buffered(buffer) => Ok(Box::new(Cursor::new(buffer.clone()))),
+    }
+}
+
+/// Opens the file, or stdin if the path is [`STDIO_PATH`].
+fn open_file_or_stdin(path: &Path) -> anyhow::Result<Box<dyn BufRead>> {
+    if is_stdio(path) {
+        open_stdin()
+    } else {
+        let file = File::open(path).with_context(|| format!("Failed to open file {:?}", path))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Creates the file, or returns stdout if the path is [`STDIO_PATH`].
+fn create_file_or_stdout(path: &Path) -> anyhow::Result<Box<dyn FinishWrite>> {
+    if is_stdio(path) {
+        let mut stdout_created = STDOUT_CREATED.lock().unwrap();
+        if *stdout_created {
+            anyhow::bail!("Stdout was already written and cannot be written again");
+        }
+        *stdout_created = true;
+        Ok(Box::new(std::io::stdout()))
+    } else {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create file {:?}", path))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// The compression formats that are supported for reading and writing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompressionFormat {
+    /// Gzip, including concatenated gzip members.
+    Gzip,
+    /// Blocked gzip as written by `bgzip`, which is read like gzip.
+    Bgzf,
+    Zstd,
+    Xz,
+}
+
+impl CompressionFormat {
+    /// Detects the compression format from the magic bytes at the start of a file.
+    fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// Selects the compression format from the file extension.
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|s| s.to_str()) {
+            Some("gz" | "gzip") => Some(Self::Gzip),
+            Some("bgz" | "bgzf") => Some(Self::Bgzf),
+            Some("zst" | "zstd") => Some(Self::Zstd),
+            Some("xz") => Some(Self::Xz),
+            _ => None,
+        }
+    }
+
+    /// Returns the given compression level or the default level of the format,
+    /// which must be within the valid range of the format.
+    fn compression_level(self, compression_level: Option<u32>) -> anyhow::Result<u32> {
+        let (default, max) = match self {
+            Self::Gzip | Self::Bgzf => (6, 9),
+            Self::Zstd => (3, 22),
+            Self::Xz => (6, 9),
+        };
+        let compression_level = compression_level.unwrap_or(default);
+        if compression_level > max {
+            anyhow::bail!(
+                "Compression level {compression_level} is too large for {self:?}, the maximum is {max}"
+            );
+        }
+        Ok(compression_level)
+    }
+
+    /// Pipes the reader through a decompressor.
+    fn decoder<'a>(self, reader: impl BufRead + 'a) -> anyhow::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::Gzip | Self::Bgzf => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            Self::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+            Self::Xz => Box::new(liblzma::bufread::XzDecoder::new_multi_decoder(reader)),
+        })
+    }
+}
+
+/// Opens the file and pipes it through a decompressor if its magic bytes indicate that it is compressed.
 pub fn read_optionally_compressed_file<T>(
     path: impl AsRef<Path>,
     reader: impl FnOnce(&mut BufReader<dyn Read>) -> anyhow::Result<T>,
@@ -15,32 +189,341 @@ pub fn read_optionally_compressed_file<T>(
     reader(&mut buf_reader)
 }
 
+/// Opens the file and pipes it through a decompressor if its magic bytes indicate that it is compressed.
+///
+/// Gzip, BGZF, zstd and xz are detected independently of the file extension.
+/// The path `-` opens stdin, which is decompressed in the same way.
 pub fn open_optionally_compressed_file(path: impl AsRef<Path>) -> anyhow::Result<Box<dyn Read>> {
     let path = path.as_ref();
-    let extension = path.extension().and_then(|s| s.to_str());
-    let file = File::open(path).with_context(|| format!("Failed to open file {:?}", path))?;
+    decompress_if_compressed(open_file_or_stdin(path)?, path)
+}
+
+/// Pipes the reader through a decompressor if its magic bytes indicate that it is compressed.
+fn decompress_if_compressed(
+    mut reader: Box<dyn BufRead>,
+    path: &Path,
+) -> anyhow::Result<Box<dyn Read>> {
+    let magic_bytes = reader
+        .fill_buf()
+        .with_context(|| format!("Failed to read file {:?}", path))?;
 
-    if extension == Some("gz") || extension == Some("gzip") {
-        let decoder = flate2::read::GzDecoder::new(file);
-        Ok(Box::new(decoder))
+    if let Some(format) = CompressionFormat::from_magic_bytes(magic_bytes) {
+        format
+            .decoder(reader)
+            .with_context(|| format!("Failed to decompress file {:?}", path))
     } else {
-        Ok(Box::new(file))
+        Ok(Box::new(reader))
     }
 }
 
+/// Creates the file like [`create_optionally_compressed_file`], passes it to the writer and finishes it afterwards.
 pub fn write_optionally_compressed_file<T>(
     path: impl AsRef<Path>,
-    writer: impl FnOnce(&mut BufWriter<dyn Write>) -> anyhow::Result<T>,
+    compression_level: Option<u32>,
+    writer: impl FnOnce(&mut OutputWriter) -> anyhow::Result<T>,
 ) -> anyhow::Result<T> {
     let path = path.as_ref();
-    let extension = path.extension().and_then(|s| s.to_str());
+    let mut output_writer = create_optionally_compressed_file(path, compression_level)?;
+    let result = writer(&mut output_writer)?;
+    output_writer
+        .finish()
+        .with_context(|| format!("Failed to write file {:?}", path))?;
+    Ok(result)
+}
+
+/// Creates the file and pipes it through a compressor if the file extension indicates that it should be compressed.
+///
+/// The extensions `.gz` and `.gzip` select gzip, `.bgz` and `.bgzf` select BGZF, `.zst` and `.zstd` select zstd, and `.xz` selects xz.
+/// If no compression level is given, then the default level of the format is used.
+/// The path `-` writes uncompressed to stdout.
+pub fn create_optionally_compressed_file(
+    path: impl AsRef<Path>,
+    compression_level: Option<u32>,
+) -> anyhow::Result<OutputWriter> {
+    let path = path.as_ref();
+    let Some(format) = CompressionFormat::from_extension(path) else {
+        return Ok(OutputWriter::new(create_file_or_stdout(path)?));
+    };
+
+    let compression_level = format.compression_level(compression_level)?;
     let file = File::create(path).with_context(|| format!("Failed to create file {:?}", path))?;
+    Ok(OutputWriter::new(match format {
+        CompressionFormat::Gzip => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::new(compression_level),
+        )),
+        CompressionFormat::Bgzf => Box::new(BgzfEncoder::new(
+            file,
+            flate2::Compression::new(compression_level),
+        )),
+        CompressionFormat::Zstd => Box::new(
+            zstd::Encoder::new(file, compression_level.try_into().unwrap())
+                .with_context(|| format!("Failed to create zstd encoder for file {:?}", path))?,
+        ),
+        CompressionFormat::Xz => Box::new(liblzma::write::XzEncoder::new(file, compression_level)),
+    }))
+}
 
-    if extension == Some("gz") || extension == Some("gzip") {
-        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
-        let mut buf_writer = BufWriter::new(encoder);
-        writer(&mut buf_writer)
-    } else {
-        writer(&mut BufWriter::new(file))
+/// A buffered writer of a file that may be compressed.
+///
+/// It must be finished with [`OutputWriter::finish`] after the last write,
+/// since dropping it would not write the end of a compressed file.
+pub struct OutputWriter {
+    writer: BufWriter<Box<dyn FinishWrite>>,
+}
+
+impl OutputWriter {
+    fn new(writer: Box<dyn FinishWrite>) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+        }
+    }
+
+    /// Flushes the buffer and writes the end of the compressed file, if any.
+    pub fn finish(self) -> std::io::Result<()> {
+        self.writer
+            .into_inner()
+            .map_err(IntoInnerError::into_error)?
+            .finish()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A writer that has to be finished after the last write, like the encoders of the compression formats.
+trait FinishWrite: Write {
+    /// Writes the end of the format, if any, and flushes the underlying writer.
+    fn finish(self: Box<Self>) -> std::io::Result<()>;
+}
+
+impl FinishWrite for File {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl FinishWrite for Stdout {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for flate2::write::GzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        flate2::write::GzEncoder::finish(*self)?.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for zstd::Encoder<'_, W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        zstd::Encoder::finish(*self)?.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for liblzma::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> std::io::Result<()> {
+        liblzma::write::XzEncoder::finish(*self)?.flush()
+    }
+}
+
+/// The maximum number of uncompressed bytes in a BGZF block, as used by `bgzip`.
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file.
+const BGZF_EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Writes the BGZF format, which is a series of gzip members that each contain at most [`BGZF_BLOCK_SIZE`] bytes.
+struct BgzfEncoder<W: Write> {
+    writer: W,
+    compression: flate2::Compression,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> BgzfEncoder<W> {
+    fn new(writer: W, compression: flate2::Compression) -> Self {
+        Self {
+            writer,
+            compression,
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+        }
+    }
+
+    /// Writes the buffered bytes as one block.
+    fn write_block(&mut self) -> std::io::Result<()> {
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), self.compression);
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let mut crc = flate2::Crc::new();
+        crc.update(&self.buffer);
+
+        // The block size minus one is stored in the extra field of the gzip header.
+        let block_size = u16::try_from(18 + compressed.len() + 8 - 1)
+            .expect("A compressed BGZF block must not be larger than 64KiB");
+        self.writer.write_all(&[
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+            0x02, 0x00,
+        ])?;
+        self.writer.write_all(&block_size.to_le_bytes())?;
+        self.writer.write_all(&compressed)?;
+        self.writer.write_all(&crc.sum().to_le_bytes())?;
+        self.writer.write_all(&crc.amount().to_le_bytes())?;
+
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_block()?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for BgzfEncoder<W> {
+    fn finish(mut self: Box<Self>) -> std::io::Result<()> {
+        self.flush()?;
+        self.writer.write_all(&BGZF_EOF_BLOCK)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use super::*;
+
+    #[test]
+    fn compression_format_from_magic_bytes() {
+        assert_eq!(
+            CompressionFormat::from_magic_bytes(&[0x1f, 0x8b, 0x08]),
+            Some(CompressionFormat::Gzip)
+        );
+        assert_eq!(
+            CompressionFormat::from_magic_bytes(&BGZF_EOF_BLOCK),
+            Some(CompressionFormat::Gzip)
+        );
+        assert_eq!(
+            CompressionFormat::from_magic_bytes(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(CompressionFormat::Zstd)
+        );
+        assert_eq!(
+            CompressionFormat::from_magic_bytes(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+            Some(CompressionFormat::Xz)
+        );
+        assert_eq!(CompressionFormat::from_magic_bytes(b"H\tVN:Z:1.0\n"), None);
+        assert_eq!(CompressionFormat::from_magic_bytes(&[0x1f]), None);
+        assert_eq!(CompressionFormat::from_magic_bytes(&[]), None);
+    }
+
+    #[test]
+    fn compression_level_must_be_in_range() {
+        assert_eq!(CompressionFormat::Gzip.compression_level(None).unwrap(), 6);
+        assert_eq!(
+            CompressionFormat::Zstd.compression_level(Some(22)).unwrap(),
+            22
+        );
+        assert!(CompressionFormat::Xz.compression_level(Some(10)).is_err());
+    }
+
+    #[test]
+    fn bgzf_framing() {
+        let data: Vec<u8> = (0..BGZF_BLOCK_SIZE + 1000)
+            .map(|index| (index % 251) as u8)
+            .collect();
+        let mut bgzf = Vec::new();
+        let mut encoder = Box::new(BgzfEncoder::new(&mut bgzf, flate2::Compression::new(6)));
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+        assert!(bgzf.ends_with(&BGZF_EOF_BLOCK));
+
+        // Each block is a gzip member whose extra field stores the block size minus one.
+        let mut block_sizes = Vec::new();
+        let mut offset = 0;
+        while offset < bgzf.len() {
+            let block = &bgzf[offset..];
+            assert_eq!(&block[..4], &[0x1f, 0x8b, 0x08, 0x04]);
+            assert_eq!(&block[12..16], &[0x42, 0x43, 0x02, 0x00]);
+            let block_size = usize::from(u16::from_le_bytes([block[16], block[17]])) + 1;
+            let uncompressed_size =
+                u32::from_le_bytes(block[block_size - 4..block_size].try_into().unwrap());
+            block_sizes.push(uncompressed_size as usize);
+            offset += block_size;
+        }
+        assert_eq!(offset, bgzf.len());
+        assert_eq!(block_sizes, [BGZF_BLOCK_SIZE, 1000, 0]);
+
+        let mut decompressed = Vec::new();
+        flate2::read::MultiGzDecoder::new(bgzf.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn compressed_files_round_trip() {
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-io-util-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let data: Vec<u8> = (0..3 * BGZF_BLOCK_SIZE)
+            .map(|index| b"ACGT\n"[index % 5])
+            .collect();
+
+        for (name, magic_bytes) in [
+            ("plain.txt", &b"ACGT"[..]),
+            ("file.gz", &[0x1f, 0x8b][..]),
+            ("file.bgz", &BGZF_EOF_BLOCK[..16]),
+            ("file.zst", &[0x28, 0xb5, 0x2f, 0xfd][..]),
+            ("file.xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00][..]),
+        ] {
+            let path = directory.join(name);
+            write_optionally_compressed_file(&path, Some(1), |writer| {
+                writer.write_all(&data)?;
+                Ok(())
+            })
+            .unwrap();
+
+            let written = fs::read(&path).unwrap();
+            assert!(written.starts_with(magic_bytes), "{name}");
+            if name.ends_with(".bgz") {
+                assert!(written.ends_with(&BGZF_EOF_BLOCK), "{name}");
+            }
+
+            let mut read = Vec::new();
+            open_optionally_compressed_file(&path)
+                .unwrap()
+                .read_to_end(&mut read)
+                .unwrap();
+            assert!(read == data, "{name}");
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
     }
 }
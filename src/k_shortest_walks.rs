@@ -0,0 +1,175 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray,
+    index::{DirectedNodeIndex, GraphIndexInteger},
+    io::gfa1::{GfaEdgeData, GfaNodeData},
+};
+use spqr_shortest_path_index::{
+    gfa_graph_extensions::GfaNodeDataExt,
+    location::{GfaLocation, GfaNodeOffset},
+    path::{GfaPath, GfaPathLength, PathElement},
+};
+
+use crate::dijkstra::edge_length;
+
+/// A node on a partial walk from the source, with the index of the label of its predecessor on the walk.
+struct Label<IndexType> {
+    node: DirectedNodeIndex<IndexType>,
+    predecessor: Option<usize>,
+}
+
+/// Computes the `k` shortest walks from the source to the target in increasing order of length,
+/// and whether walks longer than `max_distance` were skipped.
+///
+/// Walks may repeat nodes, so in a graph with cycles there are usually `k` walks if there is one.
+/// Walks longer than `max_distance` are not explored.
+///
+/// This is Dijkstra's algorithm where each node may be closed up to `k` times,
+/// each time with the next shortest walk to it.
+/// The cost of a label is the distance from the start of the source node to the start of its node,
+/// which subtracts the overlaps of the edges.
+///
+/// With overlaps, a walk can reach the start of a node before it passes the offset of the source in the source node.
+/// Whether such a walk can be continued to a valid walk to the target depends on its exact cost,
+/// so each node may be closed up to `k` times for each cost below the source offset,
+/// and up to `k` times with costs at or beyond the source offset.
+pub fn k_shortest_walks<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    source: GfaLocation<IndexType>,
+    target: GfaLocation<IndexType>,
+    k: usize,
+    max_distance: Option<GfaPathLength<IndexType>>,
+) -> (Vec<GfaPath<IndexType>>, bool) {
+    // Costs are shifted by the source offset against distances, and summed up in `usize` to avoid overflows.
+    let max_cost =
+        max_distance.map(|max_distance| max_distance.into_usize() + source.offset().into_usize());
+    let mut cut_off = false;
+    let mut within_max_cost = |cost: usize| {
+        let within_max_cost = max_cost.is_none_or(|max_cost| cost <= max_cost);
+        cut_off |= !within_max_cost;
+        within_max_cost
+    };
+
+    let mut labels = Vec::new();
+    let mut open_list = BinaryHeap::new();
+    let mut closed_counts = HashMap::new();
+    let mut walks = Vec::new();
+
+    // The walk that stays inside the source node is not found by the search below.
+    if target.node() == source.node() && target.offset() >= source.offset() {
+        walks.push(GfaPath::new(
+            vec![PathElement::new(
+                source.node(),
+                source.offset(),
+                target.offset(),
+            )],
+            target.offset() - source.offset(),
+        ));
+    }
+
+    // Like the shortest path searches, we start from the successors of the source node to allow walks that return to the source node.
+    for outgoing_edge in graph.iter_outgoing_edges(source.node()) {
+        let successor_cost = edge_length(graph, &outgoing_edge).into_usize();
+        if within_max_cost(successor_cost) {
+            open_list.push(Reverse((successor_cost, labels.len())));
+            labels.push(Label {
+                node: outgoing_edge.to(),
+                predecessor: None,
+            });
+        }
+    }
+
+    let mut target_closed_count = 0;
+    while target_closed_count < k
+        && let Some(Reverse((cost, label_index))) = open_list.pop()
+    {
+        let node = labels[label_index].node;
+        let past_source = cost >= source.offset().into_usize();
+        let closed_count = closed_counts
+            .entry((node, (!past_source).then_some(cost)))
+            .or_insert(0);
+        if *closed_count == k {
+            continue;
+        }
+        *closed_count += 1;
+
+        // With overlaps, a walk can reach the target node before it leaves the source, which is not a valid walk to the target.
+        if node == target.node()
+            && let Some(length) =
+                (cost + target.offset().into_usize()).checked_sub(source.offset().into_usize())
+        {
+            target_closed_count += 1;
+            if within_max_cost(cost + target.offset().into_usize()) {
+                walks.push(backtrack_walk(
+                    graph,
+                    &labels,
+                    label_index,
+                    source,
+                    target,
+                    GfaPathLength::from_usize(length),
+                ));
+            }
+        }
+
+        for outgoing_edge in graph.iter_outgoing_edges(node) {
+            let successor_cost = cost + edge_length(graph, &outgoing_edge).into_usize();
+            if within_max_cost(successor_cost) {
+                open_list.push(Reverse((successor_cost, labels.len())));
+                labels.push(Label {
+                    node: outgoing_edge.to(),
+                    predecessor: Some(label_index),
+                });
+            }
+        }
+    }
+
+    // The walk inside the source node may be longer than some of the walks found by the search.
+    walks.sort_by_key(GfaPath::length);
+    walks.truncate(k);
+    (walks, cut_off)
+}
+
+fn backtrack_walk<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    labels: &[Label<IndexType>],
+    label_index: usize,
+    source: GfaLocation<IndexType>,
+    target: GfaLocation<IndexType>,
+    length: GfaPathLength<IndexType>,
+) -> GfaPath<IndexType> {
+    let full_node = |node: DirectedNodeIndex<IndexType>| -> GfaNodeOffset<IndexType> {
+        GfaNodeDataExt::<IndexType>::len(graph.node_data(node.into_bidirected())).into_offset()
+    };
+
+    let mut walk = vec![PathElement::new(
+        target.node(),
+        GfaNodeOffset::from_usize(0),
+        target.offset(),
+    )];
+    let mut current_label = labels[label_index].predecessor;
+    while let Some(label_index) = current_label {
+        let node = labels[label_index].node;
+        walk.push(PathElement::new(
+            node,
+            GfaNodeOffset::from_usize(0),
+            full_node(node),
+        ));
+        current_label = labels[label_index].predecessor;
+    }
+    walk.push(PathElement::new(
+        source.node(),
+        source.offset(),
+        full_node(source.node()),
+    ));
+    walk.reverse();
+
+    GfaPath::new(walk, length)
+}
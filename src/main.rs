@@ -1,11 +1,26 @@
 use clap::{Parser, Subcommand};
 
+mod decompose;
+mod dijkstra;
+mod generate_queries;
+mod gfa;
 mod index;
+mod inspect_index;
 mod io_util;
+mod k_shortest_walks;
+mod matrix;
+mod overlay;
 mod query;
+mod serve;
+mod spqr_decomposition;
 mod statistics;
+mod verify;
+mod word_size;
 
 #[derive(Parser)]
+#[clap(
+    after_help = "Any input file can be given as `-` to read it from stdin, and any output file as `-` to write it to stdout."
+)]
 struct Cli {
     #[clap(subcommand)]
     command: CliCommand,
@@ -13,9 +28,15 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum CliCommand {
+    Decompose(decompose::Cli),
+    GenerateQueries(generate_queries::Cli),
     Index(index::Cli),
+    InspectIndex(inspect_index::Cli),
+    Matrix(matrix::Cli),
     Query(query::Cli),
+    Serve(serve::Cli),
     Statistics(statistics::Cli),
+    Verify(verify::Cli),
 }
 
 fn main() -> anyhow::Result<()> {
@@ -23,9 +44,15 @@ fn main() -> anyhow::Result<()> {
     initialise_logging(&cli);
 
     match cli.command {
+        CliCommand::Decompose(cli) => decompose::run(cli)?,
+        CliCommand::GenerateQueries(cli) => generate_queries::run(cli)?,
         CliCommand::Index(cli) => index::run(cli)?,
+        CliCommand::InspectIndex(cli) => inspect_index::run(cli)?,
+        CliCommand::Matrix(cli) => matrix::run(cli)?,
         CliCommand::Query(cli) => query::run(cli)?,
+        CliCommand::Serve(cli) => serve::run(cli)?,
         CliCommand::Statistics(cli) => statistics::run(cli)?,
+        CliCommand::Verify(cli) => verify::run(cli)?,
     }
 
     Ok(())
@@ -33,16 +60,23 @@ fn main() -> anyhow::Result<()> {
 
 fn initialise_logging(cli: &Cli) {
     let log_level = match &cli.command {
+        CliCommand::Decompose(cli) => cli.log_level,
+        CliCommand::GenerateQueries(cli) => cli.log_level,
         CliCommand::Index(cli) => cli.log_level,
+        CliCommand::InspectIndex(cli) => cli.log_level,
+        CliCommand::Matrix(cli) => cli.log_level,
         CliCommand::Query(cli) => cli.log_level,
+        CliCommand::Serve(cli) => cli.log_level,
         CliCommand::Statistics(cli) => cli.log_level,
+        CliCommand::Verify(cli) => cli.log_level,
     };
 
+    // Log to stderr, since stdout may be used for output files.
     use simplelog::*;
     CombinedLogger::init(vec![TermLogger::new(
         log_level,
         Config::default(),
-        TerminalMode::Stdout,
+        TerminalMode::Stderr,
         ColorChoice::Auto,
     )])
     .unwrap();
@@ -0,0 +1,387 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray, index::GraphIndexInteger, io::gfa1::PlainGfaEdgeData,
+};
+use clap::{Parser, ValueEnum};
+use indicatif::ProgressBar;
+use itertools::Itertools;
+use log::{LevelFilter, info, warn};
+use spqr_shortest_path_index::location::GfaLocation;
+use spqr_tree::{decomposition::SPQRDecomposition, graph::StaticGraph};
+
+use crate::{
+    dijkstra::{GfaDijkstra, OverlayDijkstra},
+    gfa::{GfaSegmentData, read_gfa_file},
+    index::{IndexHeader, read_index_header},
+    io_util::{
+        create_optionally_compressed_file, ensure_stdio_used_at_most_once,
+        open_optionally_compressed_file,
+    },
+    overlay::Overlay,
+    query::{Query, QueryParser, ShortestPathSearch, execute_queries},
+    word_size::select_word_size,
+};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph to index.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The index file.
+    /// If no index is given, then the distances will be computed with Dijkstra on the input graph.
+    #[clap(long)]
+    index_in: Option<PathBuf>,
+
+    /// The integer size to use in all data structures when no index is given.
+    /// Supported values are 8, 16, 32, and 64.
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    /// With an index, the word size of the index is used.
+    #[clap(long, conflicts_with = "index_in")]
+    word_size: Option<u8>,
+
+    /// A tab-separated file containing the source locations, which become the rows of the matrix.
+    /// The columns are `node_id`, `orientation`, `offset`, with one location per line.
+    #[clap(long)]
+    sources_in: PathBuf,
+
+    /// A file containing the target locations, which become the columns of the matrix, in the same format as `--sources-in`.
+    /// If not given, the source locations are used as targets, resulting in a square matrix.
+    #[clap(long)]
+    targets_in: Option<PathBuf>,
+
+    /// The output file for the distance matrix.
+    #[clap(long)]
+    matrix_out: PathBuf,
+
+    /// The format of the distance matrix.
+    #[clap(long, value_enum, default_value_t = MatrixFormat::Tsv)]
+    matrix_format: MatrixFormat,
+
+    /// The number of threads to compute the matrix rows with.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MatrixFormat {
+    /// Tab-separated values with a header row and a header column naming the locations as `node_id`, orientation and offset, e.g. `a+0`.
+    /// Unreachable targets are written as `None`.
+    Tsv,
+    /// A numpy array of 64-bit floats with one row per source.
+    /// Unreachable targets are written as infinity.
+    Npy,
+}
+
+/// The number of matrix rows that are computed and written at once.
+const MATRIX_BATCH_SIZE: usize = 1024;
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once(
+        [
+            Some(cli.graph_gfa_in.as_path()),
+            cli.index_in.as_deref(),
+            Some(cli.sources_in.as_path()),
+            cli.targets_in.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+        [cli.matrix_out.as_path()],
+    )?;
+
+    if cli.index_in.is_none() {
+        warn!(
+            "No index file provided, computing distances with Dijkstra on the input graph. This may be very slow for large graphs."
+        );
+        return match select_word_size(&cli.graph_gfa_in, cli.word_size)? {
+            8 => run_without_index::<u8>(cli),
+            16 => run_without_index::<u16>(cli),
+            32 => run_without_index::<u32>(cli),
+            64 => run_without_index::<u64>(cli),
+            word_size => unreachable!("Unsupported word size: {word_size}"),
+        };
+    }
+
+    let (index_header, index_file_reader) = read_index_header(cli.index_in.as_ref().unwrap())?;
+
+    match index_header.word_size {
+        8 => run_with_word_size::<u8>(cli, index_header, index_file_reader),
+        16 => run_with_word_size::<u16>(cli, index_header, index_file_reader),
+        32 => run_with_word_size::<u32>(cli, index_header, index_file_reader),
+        64 => run_with_word_size::<u64>(cli, index_header, index_file_reader),
+        _ => anyhow::bail!(
+            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
+            index_header.word_size
+        ),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
+    cli: Cli,
+    index_header: IndexHeader,
+    mut index_file_reader: impl BufRead,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    index_header.check_graph(&graph, cli.index_in.as_deref().unwrap())?;
+
+    info!("Reading index from file {:?}", cli.index_in);
+    let spqr_decomposition = SPQRDecomposition::read_binary(&graph, &mut index_file_reader)
+        .with_context(|| {
+            format!(
+                "Failed to read SPQR decomposition from index file: {:?}",
+                cli.index_in
+            )
+        })?;
+    index_header.check_spqr_decomposition(&spqr_decomposition, cli.index_in.as_deref().unwrap())?;
+    let overlay = Overlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
+        .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
+
+    compute_matrix(&cli, &graph, || OverlayDijkstra::new(&overlay))
+}
+
+fn run_without_index<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
+    cli: Cli,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    compute_matrix(&cli, &graph, || GfaDijkstra::new(&graph))
+}
+
+/// Computes the matrix row by row, with one search from each source to all targets.
+fn compute_matrix<
+    IndexType: GraphIndexInteger + FromStr + Send + Sync,
+    Search: ShortestPathSearch<IndexType> + Send,
+>(
+    cli: &Cli,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    create_search: impl Fn() -> Search,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Building node name index");
+    let query_parser = QueryParser::new(graph);
+
+    let sources = read_locations(&cli.sources_in, &query_parser)?;
+    let targets = if let Some(targets_in) = &cli.targets_in {
+        read_locations(targets_in, &query_parser)?
+    } else {
+        sources.clone()
+    };
+    info!(
+        "Computing a {}x{} distance matrix",
+        sources.len(),
+        targets.len()
+    );
+    if targets.is_empty() {
+        warn!("No target locations given, the matrix rows will be empty");
+    }
+
+    info!("Initialising {} search worker(s)", cli.threads);
+    let mut workers = (0..cli.threads).map(|_| create_search()).collect_vec();
+
+    let mut writer = create_optionally_compressed_file(&cli.matrix_out, cli.compression_level)
+        .with_context(|| format!("Failed to create matrix output file {:?}", cli.matrix_out))?;
+    match cli.matrix_format {
+        MatrixFormat::Tsv => write_tsv_header(&mut writer, graph, &targets),
+        MatrixFormat::Npy => write_npy_header(&mut writer, sources.len(), targets.len()),
+    }
+    .with_context(|| format!("Failed to write matrix to file: {:?}", cli.matrix_out))?;
+
+    let progress_bar =
+        ProgressBar::new(sources.len().try_into().unwrap()).with_message("Computing matrix");
+    for source_batch in sources.chunks(MATRIX_BATCH_SIZE) {
+        let mut rows = source_batch
+            .iter()
+            .map(|&source| Query::new(source, targets.clone()))
+            .collect_vec();
+        if !targets.is_empty() {
+            // Each row is a full search, so threads take one row at a time.
+            execute_queries(&mut workers, &mut rows, 1, &|search, row| {
+                row.execute(search, false);
+            });
+        }
+
+        match cli.matrix_format {
+            MatrixFormat::Tsv => write_tsv_rows(&mut writer, graph, &rows),
+            MatrixFormat::Npy => write_npy_rows(&mut writer, &rows),
+        }
+        .and_then(|()| Ok(writer.flush()?))
+        .with_context(|| format!("Failed to write matrix to file: {:?}", cli.matrix_out))?;
+        progress_bar.inc(rows.len().try_into().unwrap());
+    }
+    progress_bar.finish_and_clear();
+    writer
+        .finish()
+        .with_context(|| format!("Failed to write matrix to file: {:?}", cli.matrix_out))?;
+
+    info!("Wrote distance matrix to file {:?}", cli.matrix_out);
+    Ok(())
+}
+
+/// Reads a location list with one tab-separated location per line.
+/// Empty lines are ignored.
+fn read_locations<IndexType: GraphIndexInteger + FromStr>(
+    path: &Path,
+    query_parser: &QueryParser<'_, IndexType>,
+) -> anyhow::Result<Vec<GfaLocation<IndexType>>>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading locations from file {:?}", path);
+    let reader = BufReader::new(
+        open_optionally_compressed_file(path)
+            .with_context(|| format!("Failed to open location file {:?}", path))?,
+    );
+
+    let mut locations = Vec::new();
+    for (line_index, line) in reader.lines().enumerate() {
+        let line =
+            line.with_context(|| format!("Failed to read line from location file: {:?}", path))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let location = match line.split('\t').collect_vec().as_slice() {
+            [node_name, orientation, offset] => {
+                query_parser.parse_location(node_name, orientation, offset)
+            }
+            columns => Err(anyhow::anyhow!("expected 3 columns, got {}", columns.len())),
+        }
+        .with_context(|| {
+            format!(
+                "Invalid location in file {:?} at line {}",
+                path,
+                line_index + 1
+            )
+        })?;
+        locations.push(location);
+    }
+
+    Ok(locations)
+}
+
+/// Formats a location as node name, orientation and offset, e.g. `a+0`.
+pub(crate) fn format_location<IndexType: GraphIndexInteger>(
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    location: GfaLocation<IndexType>,
+) -> String {
+    format!(
+        "{}{}{}",
+        graph.node_name(location.node().into_bidirected()),
+        if location.node().is_forward() {
+            "+"
+        } else {
+            "-"
+        },
+        location.offset(),
+    )
+}
+
+fn write_tsv_header<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    targets: &[GfaLocation<IndexType>],
+) -> anyhow::Result<()> {
+    for &target in targets {
+        write!(writer, "\t{}", format_location(graph, target))?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn write_tsv_rows<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    rows: &[Query<IndexType>],
+) -> anyhow::Result<()> {
+    for row in rows {
+        write!(writer, "{}", format_location(graph, row.source()))?;
+        for distance in row.distances() {
+            if let Some(distance) = distance.into_option() {
+                write!(writer, "\t{distance}")?;
+            } else {
+                write!(writer, "\tNone")?;
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes the header of a version 1.0 `.npy` file for a C-order matrix of little-endian 64-bit floats.
+fn write_npy_header(
+    writer: &mut impl Write,
+    row_count: usize,
+    column_count: usize,
+) -> anyhow::Result<()> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({row_count}, {column_count}), }}"
+    );
+    // The header is padded with spaces and terminated by a newline, such that the data starts at a multiple of 64 bytes.
+    let unpadded_length = MAGIC.len() + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded_length.next_multiple_of(64) - unpadded_length,
+    ));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&u16::try_from(header.len())?.to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    Ok(())
+}
+
+fn write_npy_rows<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    rows: &[Query<IndexType>],
+) -> anyhow::Result<()> {
+    for row in rows {
+        for distance in row.distances() {
+            let distance = distance
+                .into_option()
+                .map(|distance| distance.into_usize() as f64)
+                .unwrap_or(f64::INFINITY);
+            writer.write_all(&distance.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,509 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    iter,
+    sync::Mutex,
+    thread,
+};
+
+use bidirected_adjacency_array::{
+    graph::{BidirectedAdjacencyArray, BidirectedEdge},
+    index::{DirectedNodeIndex, GraphIndexInteger, NodeIndex, OptionalNodeIndex},
+    io::gfa1::{GfaEdgeData, GfaNodeData},
+};
+use log::info;
+use spqr_shortest_path_index::{gfa_graph_extensions::GfaNodeDataExt, path::GfaPathLength};
+use spqr_tree::decomposition::{Block, SPQRDecomposition, indices::BlockIndex};
+use tagged_vec::TaggedVec;
+
+use crate::dijkstra::GfaDijkstra;
+
+/// The overlay of the SPQR decomposition, which stores the distances that allow index searches to skip over parts of the graph.
+///
+/// Its nodes are the cut nodes and the nodes that are incident to virtual edges.
+/// Its edges form two levels: the SPQR-tree level connects the overlay nodes of each SPQR node,
+/// and the block-cut-tree level connects the cut nodes of each block.
+/// The length of an overlay edge is the length of a shortest walk from the start of its first node to the end of its second node,
+/// which stays the same when the walk is traversed in reverse.
+pub struct Overlay<
+    'graph,
+    'spqr,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+> {
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    spqr_decomposition:
+        &'spqr SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    spqr_tree_level: OverlayLevel<IndexType>,
+    block_cut_tree_level: OverlayLevel<IndexType>,
+    /// Maps the nodes of the graph to the nodes of the overlay, if they exist.
+    graph_to_overlay_node_map: TaggedVec<NodeIndex<IndexType>, OptionalNodeIndex<IndexType>>,
+}
+
+/// The edges of one level of the overlay.
+///
+/// Both levels have the same nodes, whose data are the corresponding nodes of the graph.
+/// The data of an edge is its length.
+type OverlayLevel<IndexType> =
+    BidirectedAdjacencyArray<IndexType, NodeIndex<IndexType>, GfaPathLength<IndexType>>;
+
+/// An overlay edge as a pair of directed nodes of the graph together with its length.
+type OverlayEdge<IndexType> = (
+    DirectedNodeIndex<IndexType>,
+    DirectedNodeIndex<IndexType>,
+    GfaPathLength<IndexType>,
+);
+
+impl<'graph, 'spqr, IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>
+    Overlay<'graph, 'spqr, IndexType, NodeData, EdgeData>
+{
+    /// Builds the overlay of the SPQR decomposition using the given number of threads.
+    ///
+    /// The blocks are processed in parallel,
+    /// and the shortest path searches are skipped for nodes whose distances are known.
+    pub fn build(
+        graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        spqr_decomposition: &'spqr SPQRDecomposition<
+            'graph,
+            BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        >,
+        threads: u16,
+        known_distances: Option<&KnownDistances<IndexType>>,
+    ) -> Self
+    where
+        IndexType: Send + Sync,
+        NodeData: Sync,
+        EdgeData: Sync,
+    {
+        let mut nodes = TaggedVec::<NodeIndex<IndexType>, _>::new();
+        let mut graph_to_overlay_node_map = TaggedVec::from_iter(iter::repeat_n(
+            OptionalNodeIndex::new_none(),
+            graph.node_count(),
+        ));
+        for node in graph.iter_nodes() {
+            if spqr_decomposition.is_cut_node(node)
+                || spqr_decomposition.has_incident_virtual_edge(node)
+            {
+                nodes.push(node);
+                graph_to_overlay_node_map[node] = OptionalNodeIndex::from_usize(nodes.len() - 1);
+            }
+        }
+
+        let mut tasks: Vec<_> = spqr_decomposition
+            .iter_blocks()
+            .map(|(block_index, block)| BlockTask {
+                block_index,
+                block,
+                spqr_tree_edges: Vec::new(),
+                block_cut_tree_edges: Vec::new(),
+            })
+            .collect();
+        info!(
+            "Computing overlay edges for {} blocks with {} threads",
+            tasks.len(),
+            threads
+        );
+
+        // Threads take tasks from a shared iterator, and the edges are assembled in block order afterwards,
+        // so the overlay does not depend on the number of threads.
+        let remaining_tasks = Mutex::new(tasks.iter_mut());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let remaining_tasks = &remaining_tasks;
+                scope.spawn(move || {
+                    let mut dijkstra = GfaDijkstra::new(graph);
+                    loop {
+                        let next_task = remaining_tasks.lock().unwrap().next();
+                        let Some(task) = next_task else {
+                            break;
+                        };
+
+                        task.execute(spqr_decomposition, &mut dijkstra, known_distances);
+                    }
+                });
+            }
+        });
+
+        info!("Assembling overlay");
+        let overlay_edge = |(from, to, length): OverlayEdge<IndexType>| {
+            let overlay_node = |node: DirectedNodeIndex<IndexType>| {
+                node.with_bidirected_node_index(
+                    graph_to_overlay_node_map[node.into_bidirected()]
+                        .expect("nodes of overlay edges have an overlay node"),
+                )
+            };
+            BidirectedEdge::new(overlay_node(from), overlay_node(to), length)
+        };
+        let mut spqr_tree_edges = TaggedVec::new();
+        let mut block_cut_tree_edges = TaggedVec::new();
+        for task in tasks {
+            for edge in task.spqr_tree_edges {
+                spqr_tree_edges.push(overlay_edge(edge));
+            }
+            for edge in task.block_cut_tree_edges {
+                block_cut_tree_edges.push(overlay_edge(edge));
+            }
+        }
+
+        Self {
+            graph,
+            spqr_decomposition,
+            spqr_tree_level: BidirectedAdjacencyArray::new(nodes.clone(), spqr_tree_edges),
+            block_cut_tree_level: BidirectedAdjacencyArray::new(nodes, block_cut_tree_edges),
+            graph_to_overlay_node_map,
+        }
+    }
+
+    /// Reads the overlay from the platform-dependent binary format written by [`Self::write_binary`].
+    pub fn read_binary(
+        graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        spqr_decomposition: &'spqr SPQRDecomposition<
+            'graph,
+            BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        >,
+        mut reader: impl Read,
+    ) -> std::io::Result<Self> {
+        let spqr_tree_level = OverlayLevel::read_binary(&mut reader)?;
+        let block_cut_tree_level = OverlayLevel::read_binary(&mut reader)?;
+
+        let invalid_data = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        if spqr_tree_level.node_count() != block_cut_tree_level.node_count() {
+            return Err(invalid_data("The overlay levels have different nodes"));
+        }
+        let mut graph_to_overlay_node_map = TaggedVec::from_iter(iter::repeat_n(
+            OptionalNodeIndex::new_none(),
+            graph.node_count(),
+        ));
+        for overlay_node in spqr_tree_level.iter_nodes() {
+            let node = *spqr_tree_level.node_data(overlay_node);
+            if node != *block_cut_tree_level.node_data(overlay_node) {
+                return Err(invalid_data("The overlay levels have different nodes"));
+            }
+            if node.into_usize() >= graph.node_count() {
+                return Err(invalid_data("An overlay node does not exist in the graph"));
+            }
+            graph_to_overlay_node_map[node] =
+                OptionalNodeIndex::from_usize(overlay_node.into_usize());
+        }
+
+        Ok(Self {
+            graph,
+            spqr_decomposition,
+            spqr_tree_level,
+            block_cut_tree_level,
+            graph_to_overlay_node_map,
+        })
+    }
+
+    /// Writes the overlay into a platform-dependent binary format.
+    ///
+    /// The format consists of the SPQR-tree level followed by the block-cut-tree level,
+    /// both as bidirected adjacency arrays in their binary format.
+    pub fn write_binary(&self, mut writer: impl Write) -> std::io::Result<()> {
+        self.spqr_tree_level.write_binary(&mut writer)?;
+        self.block_cut_tree_level.write_binary(&mut writer)
+    }
+
+    pub fn graph(&self) -> &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData> {
+        self.graph
+    }
+
+    pub fn spqr_decomposition(
+        &self,
+    ) -> &'spqr SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>
+    {
+        self.spqr_decomposition
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.spqr_tree_level.node_count()
+    }
+
+    /// Returns the number of bidirected edges in both levels of the overlay.
+    pub fn edge_count(&self) -> usize {
+        self.spqr_tree_level.edge_count() + self.block_cut_tree_level.edge_count()
+    }
+
+    /// Iterates over the nodes of the graph that are nodes of the overlay.
+    pub fn iter_nodes(&self) -> impl Iterator<Item = NodeIndex<IndexType>> + '_ {
+        self.spqr_tree_level
+            .iter_nodes()
+            .map(|overlay_node| *self.spqr_tree_level.node_data(overlay_node))
+    }
+
+    /// Iterates over the SPQR-tree level edges leaving the directed node,
+    /// as pairs of their head in the graph and their length.
+    ///
+    /// Nodes that are not in the overlay have no overlay edges.
+    pub fn iter_outgoing_spqr_tree_edges(
+        &self,
+        node: DirectedNodeIndex<IndexType>,
+    ) -> impl Iterator<Item = (DirectedNodeIndex<IndexType>, GfaPathLength<IndexType>)> + '_ {
+        self.iter_outgoing_edges(&self.spqr_tree_level, node)
+    }
+
+    /// Iterates over the block-cut-tree level edges leaving the directed node,
+    /// as pairs of their head in the graph and their length.
+    ///
+    /// Nodes that are not in the overlay have no overlay edges.
+    pub fn iter_outgoing_block_cut_tree_edges(
+        &self,
+        node: DirectedNodeIndex<IndexType>,
+    ) -> impl Iterator<Item = (DirectedNodeIndex<IndexType>, GfaPathLength<IndexType>)> + '_ {
+        self.iter_outgoing_edges(&self.block_cut_tree_level, node)
+    }
+
+    fn iter_outgoing_edges<'this>(
+        &'this self,
+        level: &'this OverlayLevel<IndexType>,
+        node: DirectedNodeIndex<IndexType>,
+    ) -> impl Iterator<Item = (DirectedNodeIndex<IndexType>, GfaPathLength<IndexType>)> + 'this
+    {
+        self.graph_to_overlay_node_map[node.into_bidirected()]
+            .into_option()
+            .into_iter()
+            .flat_map(move |overlay_node| {
+                level.iter_outgoing_edges(node.with_bidirected_node_index(overlay_node))
+            })
+            .map(move |edge| {
+                let to = edge.to();
+                (
+                    to.with_bidirected_node_index(*level.node_data(to.into_bidirected())),
+                    *level.directed_edge_data(edge.index()).data(),
+                )
+            })
+    }
+}
+
+/// Shortest path lengths between overlay nodes, taken from the overlay of an old version of the graph.
+pub struct KnownDistances<IndexType> {
+    /// True for each node in a connected component that is unchanged since the old graph.
+    /// The shortest paths between the overlay nodes of such a component are the same as in the old overlay.
+    is_known: Vec<bool>,
+    /// The lengths of the overlay edges between the directed nodes of the unchanged components.
+    /// Pairs of overlay nodes without an entry are not connected.
+    distances: HashMap<
+        (DirectedNodeIndex<IndexType>, DirectedNodeIndex<IndexType>),
+        GfaPathLength<IndexType>,
+    >,
+}
+
+impl<IndexType: GraphIndexInteger> KnownDistances<IndexType> {
+    /// Collects the distances of the old overlay between the old nodes that have a node in the graph.
+    ///
+    /// The nodes of the graph must form complete connected components in both the old and the new graph.
+    pub fn new<NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+        old_overlay: &Overlay<IndexType, NodeData, EdgeData>,
+        old_to_new_nodes: &[Option<NodeIndex<IndexType>>],
+        node_count: usize,
+    ) -> Self {
+        let mut is_known = vec![false; node_count];
+        for node in old_to_new_nodes.iter().flatten() {
+            is_known[node.into_usize()] = true;
+        }
+
+        let new_node = |old_node: DirectedNodeIndex<IndexType>| {
+            old_to_new_nodes[old_node.into_bidirected().into_usize()]
+                .map(|node| old_node.with_bidirected_node_index(node))
+        };
+        let mut distances = HashMap::new();
+        for old_node in old_overlay.iter_nodes() {
+            for old_from in [
+                old_node.into_directed_forward(),
+                old_node.into_directed_reverse(),
+            ] {
+                let Some(from) = new_node(old_from) else {
+                    continue;
+                };
+                for (old_to, length) in old_overlay
+                    .iter_outgoing_spqr_tree_edges(old_from)
+                    .chain(old_overlay.iter_outgoing_block_cut_tree_edges(old_from))
+                {
+                    if let Some(to) = new_node(old_to) {
+                        distances.insert((from, to), length);
+                    }
+                }
+            }
+        }
+
+        Self {
+            is_known,
+            distances,
+        }
+    }
+}
+
+/// The shortest path searches within one block, and the overlay edges found by them.
+struct BlockTask<'spqr, IndexType> {
+    block_index: BlockIndex<IndexType>,
+    block: &'spqr Block<NodeIndex<IndexType>, IndexType>,
+    /// Edges between the nodes of each SPQR node of the block that are incident to virtual edges.
+    spqr_tree_edges: Vec<OverlayEdge<IndexType>>,
+    /// Edges between the cut nodes of the block.
+    block_cut_tree_edges: Vec<OverlayEdge<IndexType>>,
+}
+
+impl<IndexType: GraphIndexInteger> BlockTask<'_, IndexType> {
+    /// Runs the shortest path searches between the overlay nodes of the block.
+    fn execute<NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+        &mut self,
+        spqr_decomposition: &SPQRDecomposition<
+            '_,
+            BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        >,
+        dijkstra: &mut GfaDijkstra<IndexType, NodeData, EdgeData>,
+        known_distances: Option<&KnownDistances<IndexType>>,
+    ) {
+        // Nodes may share several SPQR nodes, but each pair is searched only once.
+        let mut searched_pairs = HashSet::new();
+        for (_, spqr_node) in spqr_decomposition.iter_spqr_nodes_in_block(self.block_index) {
+            let mut nodes = Vec::new();
+            for spqr_edge_index in spqr_node.iter_incident_spqr_edges() {
+                let (a, b) = spqr_decomposition.spqr_edge(spqr_edge_index).virtual_edge();
+                for node in [a, b] {
+                    if !nodes.contains(&node) {
+                        nodes.push(node);
+                    }
+                }
+            }
+
+            for (offset, &from) in nodes.iter().enumerate() {
+                for &to in &nodes[offset..] {
+                    if searched_pairs.insert((from.min(to), from.max(to))) {
+                        search(
+                            from,
+                            to,
+                            dijkstra,
+                            known_distances,
+                            &mut self.spqr_tree_edges,
+                        );
+                    }
+                }
+            }
+        }
+
+        let cut_nodes: Vec<_> = self
+            .block
+            .iter_cut_nodes()
+            .map(|cut_node| spqr_decomposition.cut_node_index_to_node_index(cut_node))
+            .collect();
+        for (offset, &from) in cut_nodes.iter().enumerate() {
+            for &to in &cut_nodes[offset..] {
+                search(
+                    from,
+                    to,
+                    dijkstra,
+                    known_distances,
+                    &mut self.block_cut_tree_edges,
+                );
+            }
+        }
+    }
+}
+
+/// Adds the overlay edges between the two nodes, or the overlay self-loops if both nodes are the same.
+fn search<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    from: NodeIndex<IndexType>,
+    to: NodeIndex<IndexType>,
+    dijkstra: &mut GfaDijkstra<IndexType, NodeData, EdgeData>,
+    known_distances: Option<&KnownDistances<IndexType>>,
+    edges: &mut Vec<OverlayEdge<IndexType>>,
+) {
+    let known_distances =
+        known_distances.filter(|known_distances| known_distances.is_known[from.into_usize()]);
+
+    let to_length = <NodeData as GfaNodeDataExt<IndexType>>::len(dijkstra.graph().node_data(to));
+    // An overlay edge also stands for its reverse, so a self-loop from the forward to the forward node
+    // covers the one from the reverse to the reverse node.
+    let pairs = if from != to {
+        [
+            Some((true, true)),
+            Some((true, false)),
+            Some((false, true)),
+            Some((false, false)),
+        ]
+    } else if to_length > 0.into() {
+        [
+            Some((true, true)),
+            Some((true, false)),
+            Some((false, true)),
+            None,
+        ]
+    } else {
+        // Self-loops starting and ending in the same orientation are never part of a shortest path if the node is empty.
+        [Some((true, false)), Some((false, true)), None, None]
+    };
+
+    for (from_forward, to_forward) in pairs.into_iter().flatten() {
+        let from = from.into_directed(from_forward);
+        let to = to.into_directed(to_forward);
+        let length = if let Some(known_distances) = known_distances {
+            known_distances.distances.get(&(from, to)).copied()
+        } else {
+            dijkstra
+                .shortest_walk(from, to)
+                .map(|(length, _)| length + to_length)
+        };
+        if let Some(length) = length {
+            edges.push((from, to, length));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{gfa::read_gfa_file, spqr_decomposition::compute_spqr_decomposition};
+
+    use super::Overlay;
+
+    #[test]
+    fn overlay_is_independent_of_threads_and_survives_binary_round_trip() {
+        let graph = read_gfa_file::<u8>(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_files/tiny1_overlaps.gfa"
+        )))
+        .unwrap();
+        let spqr_decomposition = compute_spqr_decomposition(&graph).unwrap();
+
+        let overlay = Overlay::build(&graph, &spqr_decomposition, 1, None);
+        let mut bytes = Vec::new();
+        overlay.write_binary(&mut bytes).unwrap();
+        assert!(overlay.edge_count() > 0);
+
+        let mut parallel_bytes = Vec::new();
+        Overlay::build(&graph, &spqr_decomposition, 4, None)
+            .write_binary(&mut parallel_bytes)
+            .unwrap();
+        assert!(parallel_bytes == bytes);
+
+        let read_overlay =
+            Overlay::read_binary(&graph, &spqr_decomposition, bytes.as_slice()).unwrap();
+        assert_eq!(
+            read_overlay.iter_nodes().collect::<Vec<_>>(),
+            overlay.iter_nodes().collect::<Vec<_>>(),
+        );
+        for node in graph.iter_nodes() {
+            for node in [node.into_directed_forward(), node.into_directed_reverse()] {
+                assert_eq!(
+                    read_overlay
+                        .iter_outgoing_spqr_tree_edges(node)
+                        .collect::<Vec<_>>(),
+                    overlay
+                        .iter_outgoing_spqr_tree_edges(node)
+                        .collect::<Vec<_>>(),
+                );
+                assert_eq!(
+                    read_overlay
+                        .iter_outgoing_block_cut_tree_edges(node)
+                        .collect::<Vec<_>>(),
+                    overlay
+                        .iter_outgoing_block_cut_tree_edges(node)
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+    }
+}
@@ -1,33 +1,42 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
-    io::{BufRead, BufReader, Read, Write},
+    io::{BufRead, BufReader, Write},
     path::PathBuf,
     str::FromStr,
-    time::Instant,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::Context;
 use bidirected_adjacency_array::{
     graph::BidirectedAdjacencyArray,
-    index::{DirectedNodeIndex, GraphIndexInteger},
-    io::gfa1::{PlainGfaEdgeData, PlainGfaNodeData},
+    index::{DirectedNodeIndex, GraphIndexInteger, NodeIndex},
+    io::gfa1::{GfaEdgeData, GfaNodeData, PlainGfaEdgeData},
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use log::{LevelFilter, info, warn};
 use spqr_shortest_path_index::{
-    dijkstra::GfaDijkstra,
+    gfa_graph_extensions::GfaNodeDataExt,
     location::{GfaLocation, GfaNodeOffset},
-    location_index::{multi::MultiGfaLocationIndex, single::SingleGfaLocationIndex},
-    path::OptionalGfaPathLength,
-    spqr_decomposition_overlay::{SPQRDecompositionOverlay, dijkstra::OverlayDijkstra},
+    path::{GfaPath, GfaPathLength, OptionalGfaPathLength, PathElement},
 };
 use spqr_tree::{decomposition::SPQRDecomposition, graph::StaticGraph};
 
-use crate::io_util::{
-    open_optionally_compressed_file, read_optionally_compressed_file,
-    write_optionally_compressed_file,
+use crate::{
+    dijkstra::{GfaDijkstra, OverlayDijkstra},
+    gfa::{GfaSegmentData, read_gfa_file},
+    index::{IndexHeader, read_index_header},
+    io_util::{
+        create_optionally_compressed_file, ensure_stdio_used_at_most_once,
+        open_optionally_compressed_file,
+    },
+    k_shortest_walks::k_shortest_walks,
+    overlay::Overlay,
+    word_size::select_word_size,
 };
 
 #[derive(Parser)]
@@ -39,15 +48,18 @@ pub struct Cli {
     #[clap(long)]
     graph_gfa_in: PathBuf,
 
-    /// The SPQR decomposition in plain text format.
-    #[clap(long, requires = "index_in")]
-    spqr_in: Option<PathBuf>,
-
     /// The index file.
     /// If no index is given, then the queries will be run with Dijkstra on the input graph.
-    #[clap(long, requires = "spqr_in")]
+    #[clap(long)]
     index_in: Option<PathBuf>,
 
+    /// The integer size to use in all data structures when no index is given.
+    /// Supported values are 8, 16, 32, and 64.
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    /// With an index, the word size of the index is used.
+    #[clap(long, conflicts_with = "index_in")]
+    word_size: Option<u8>,
+
     /// A tab-separated file containing the queries to run.
     /// The columns are `source_node_id`, `source_orientation`, `source_offset`, `target_node_id`, `target_orientation`, `target_offset`.
     /// The last three columns can be repeated to specify multiple target locations for the same source.
@@ -58,387 +70,1272 @@ pub struct Cli {
     /// Contains a copy of the input rows with and additional column for the `distance` for each target.
     #[clap(long)]
     query_out: PathBuf,
+
+    /// Additionally output the shortest path for each source/target pair.
+    /// This adds three columns after each `distance`: the oriented node walk (e.g. `a+,c+,d+`),
+    /// the offset where the walk enters its first node and the offset where it leaves its last node.
+    #[clap(long)]
+    query_paths: bool,
+
+    /// An optional output file for the shortest paths in GAF format.
+    /// Each source/target pair with a path becomes one record, named `<line number>_<target number>`,
+    /// where both numbers count from one, like the line numbers in error messages and in `--invalid-query-out`.
+    /// Unreachable targets are omitted.
+    #[clap(long)]
+    query_gaf_out: Option<PathBuf>,
+
+    /// The number of threads to execute queries with.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// What to do with query lines that cannot be parsed or refer to invalid locations.
+    #[clap(long, value_enum, default_value_t = InvalidQueryPolicy::Error)]
+    on_invalid_query: InvalidQueryPolicy,
+
+    /// An optional output file for invalid query lines, which requires `--on-invalid-query report`.
+    /// Each rejected line is written as its one-based line number, the error and the original line, separated by tabs.
+    #[clap(long)]
+    invalid_query_out: Option<PathBuf>,
+
+    /// Only search for targets up to this distance from the source.
+    /// Targets that are farther away are reported with a distance of `>max_distance`, and no path.
+    /// Targets that are unreachable are reported as `None` if the search explored everything reachable within the maximum distance,
+    /// and as `>max_distance` if the search was cut off, since they may be reachable over a longer walk.
+    #[clap(long)]
+    max_distance: Option<u64>,
+
+    /// Interpret the last column of each query line as the maximum distance for that query.
+    /// It overrides `--max-distance` for the query.
+    #[clap(long)]
+    max_distance_column: bool,
+
+    /// Compute the K shortest walks to each target instead of only the shortest path.
+    /// The distance column then contains the comma-separated lengths of the walks in increasing order,
+    /// and the path columns (see `--query-paths`) contain the semicolon-separated walks.
+    /// The walks are searched in the graph itself, since the index only stores shortest paths,
+    /// so this cannot be used with `--index-in`.
+    #[clap(long, conflicts_with = "index_in", value_parser = clap::value_parser!(u32).range(1..))]
+    k_shortest: Option<u32>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InvalidQueryPolicy {
+    /// Abort on the first invalid query.
+    Error,
+    /// Silently skip invalid queries.
+    Skip,
+    /// Skip invalid queries and log each of them as a warning.
+    Report,
+}
+
+/// The number of queries that are read, executed and written at once.
+const QUERY_BATCH_SIZE: usize = 1 << 16;
+
+/// The number of queries that a thread takes at once.
+const QUERY_CHUNK_SIZE: usize = 1024;
+
+impl Cli {
+    fn computes_paths(&self) -> bool {
+        self.query_paths || self.query_gaf_out.is_some()
+    }
 }
 
-struct Query<IndexType> {
+pub(crate) struct Query<IndexType> {
     source: GfaLocation<IndexType>,
     targets: Vec<GfaLocation<IndexType>>,
     distances: Vec<OptionalGfaPathLength<IndexType>>,
+    /// Only filled if paths were requested.
+    paths: Vec<Option<GfaPath<IndexType>>>,
+    /// If set, targets farther away than this are not searched for.
+    max_distance: Option<GfaPathLength<IndexType>>,
+    /// Only filled if the k shortest walks were requested.
+    walks: Vec<Vec<GfaPath<IndexType>>>,
+    /// For each target, whether its search skipped walks longer than the maximum distance.
+    /// Targets without a distance are farther away than the maximum distance if their search was cut off,
+    /// and unreachable otherwise.
+    cut_off: Vec<bool>,
+}
+
+/// Parses queries and locations, validating them against the graph.
+pub(crate) struct QueryParser<'graph, IndexType: GraphIndexInteger> {
+    graph: &'graph BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    node_name_index: HashMap<Cow<'graph, str>, NodeIndex<IndexType>>,
+    max_distance_column: bool,
+}
+
+/// The shortest path searches that queries can be executed with.
+pub(crate) trait ShortestPathSearch<IndexType> {
+    fn shortest_paths(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>>;
+
+    /// Computes the distances to all targets that are at most `max_distance` away from the source,
+    /// and whether the search skipped walks longer than `max_distance`.
+    /// The search does not explore the graph beyond `max_distance`.
+    fn bounded_distances(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: GfaPathLength<IndexType>,
+    ) -> (
+        HashMap<GfaLocation<IndexType>, GfaPathLength<IndexType>>,
+        bool,
+    );
+}
+
+impl<IndexType: GraphIndexInteger> Query<IndexType> {
+    pub(crate) fn new(
+        source: GfaLocation<IndexType>,
+        targets: Vec<GfaLocation<IndexType>>,
+    ) -> Self {
+        Self {
+            source,
+            targets,
+            distances: Vec::new(),
+            paths: Vec::new(),
+            max_distance: None,
+            walks: Vec::new(),
+            cut_off: Vec::new(),
+        }
+    }
+
+    pub(crate) fn source(&self) -> GfaLocation<IndexType> {
+        self.source
+    }
+
+    pub(crate) fn targets(&self) -> &[GfaLocation<IndexType>] {
+        &self.targets
+    }
+
+    /// The distances to the targets, available after the query was executed.
+    pub(crate) fn distances(&self) -> &[OptionalGfaPathLength<IndexType>] {
+        &self.distances
+    }
+
+    /// Computes the distances from the source to all targets, and returns the shortest paths.
+    ///
+    /// If the query has a maximum distance, then paths are only returned if `compute_paths` is set,
+    /// and only for the targets within the maximum distance.
+    pub(crate) fn execute(
+        &mut self,
+        search: &mut impl ShortestPathSearch<IndexType>,
+        compute_paths: bool,
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        let Some(max_distance) = self.max_distance else {
+            let paths = search.shortest_paths(self.source, &self.targets);
+            self.distances = self
+                .targets
+                .iter()
+                .map(|&target| paths.get(&target).map(|path| path.length()).into())
+                .collect();
+            return paths;
+        };
+
+        let (distances, cut_off) =
+            search.bounded_distances(self.source, &self.targets, max_distance);
+        self.distances = self
+            .targets
+            .iter()
+            .map(|target| distances.get(target).copied().into())
+            .collect();
+        self.cut_off = vec![cut_off; self.targets.len()];
+
+        // The searches for the paths stop early, since all remaining targets are within the maximum distance.
+        let found_targets = self
+            .targets
+            .iter()
+            .filter(|target| distances.contains_key(target))
+            .copied()
+            .collect_vec();
+        if compute_paths && !found_targets.is_empty() {
+            search.shortest_paths(self.source, &found_targets)
+        } else {
+            HashMap::new()
+        }
+    }
+}
+
+impl<IndexType: GraphIndexInteger> Query<IndexType> {
+    /// Computes the `k` shortest walks from the source to each target.
+    pub(crate) fn execute_k_shortest<NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+        &mut self,
+        graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        k: usize,
+    ) {
+        (self.walks, self.cut_off) = self
+            .targets
+            .iter()
+            .map(|&target| k_shortest_walks(graph, self.source, target, k, self.max_distance))
+            .unzip();
+        self.distances = self
+            .walks
+            .iter()
+            .map(|walks| walks.first().map(GfaPath::length).into())
+            .collect();
+    }
+
+    /// The k shortest walks to the target if they were requested, and otherwise its shortest path if it was requested.
+    fn target_paths(&self, target_index: usize) -> Vec<&GfaPath<IndexType>> {
+        if let Some(walks) = self.walks.get(target_index) {
+            walks.iter().collect()
+        } else {
+            self.paths
+                .get(target_index)
+                .and_then(Option::as_ref)
+                .into_iter()
+                .collect()
+        }
+    }
+}
+
+impl<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>
+    ShortestPathSearch<IndexType> for GfaDijkstra<'_, IndexType, NodeData, EdgeData>
+{
+    fn shortest_paths(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        GfaDijkstra::shortest_paths(self, source, targets, None)
+    }
+
+    fn bounded_distances(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: GfaPathLength<IndexType>,
+    ) -> (
+        HashMap<GfaLocation<IndexType>, GfaPathLength<IndexType>>,
+        bool,
+    ) {
+        let distances = GfaDijkstra::shortest_paths(self, source, targets, Some(max_distance))
+            .into_iter()
+            .map(|(target, path)| (target, path.length()))
+            .collect();
+        (distances, self.is_cut_off())
+    }
+}
+
+impl<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>
+    ShortestPathSearch<IndexType> for OverlayDijkstra<'_, '_, '_, IndexType, NodeData, EdgeData>
+{
+    fn shortest_paths(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+    ) -> HashMap<GfaLocation<IndexType>, GfaPath<IndexType>> {
+        OverlayDijkstra::shortest_paths(self, source, targets, None)
+    }
+
+    fn bounded_distances(
+        &mut self,
+        source: GfaLocation<IndexType>,
+        targets: &[GfaLocation<IndexType>],
+        max_distance: GfaPathLength<IndexType>,
+    ) -> (
+        HashMap<GfaLocation<IndexType>, GfaPathLength<IndexType>>,
+        bool,
+    ) {
+        let distances = OverlayDijkstra::shortest_paths(self, source, targets, Some(max_distance))
+            .into_iter()
+            .map(|(target, path)| (target, path.length()))
+            .collect();
+        (distances, self.is_cut_off())
+    }
 }
 
 pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once(
+        [
+            Some(cli.graph_gfa_in.as_path()),
+            cli.index_in.as_deref(),
+            Some(cli.query_in.as_path()),
+        ]
+        .into_iter()
+        .flatten(),
+        [
+            Some(cli.query_out.as_path()),
+            cli.query_gaf_out.as_deref(),
+            cli.invalid_query_out.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+    )?;
+    if cli.invalid_query_out.is_some() && cli.on_invalid_query != InvalidQueryPolicy::Report {
+        anyhow::bail!("--invalid-query-out can only be used with --on-invalid-query report");
+    }
+
     if cli.index_in.is_none() {
         warn!(
             "No index file provided, running queries with Dijkstra on the input graph. This may be very slow for large graphs."
         );
-        return run_without_index::<u64>(cli);
+        return match select_word_size(&cli.graph_gfa_in, cli.word_size)? {
+            8 => run_without_index::<u8>(cli),
+            16 => run_without_index::<u16>(cli),
+            32 => run_without_index::<u32>(cli),
+            64 => run_without_index::<u64>(cli),
+            word_size => unreachable!("Unsupported word size: {word_size}"),
+        };
     }
 
-    // Read word size from index file first.
-    let mut index_file_reader = BufReader::new(
-        open_optionally_compressed_file(cli.index_in.as_ref().unwrap())
-            .with_context(|| format!("Failed to open index file {:?}", cli.index_in))?,
-    );
-    let mut word_size_bytes = [0u8; 1];
-    index_file_reader
-        .read_exact(&mut word_size_bytes)
-        .with_context(|| format!("Failed to read index header from file {:?}", cli.index_in))?;
-    let word_size = word_size_bytes[0];
+    let (index_header, index_file_reader) = read_index_header(cli.index_in.as_ref().unwrap())?;
 
-    info!(
-        "Discovered word size {} bits from index file header",
-        word_size
-    );
-
-    match word_size {
-        8 => run_with_word_size::<u8>(cli, index_file_reader),
-        16 => run_with_word_size::<u16>(cli, index_file_reader),
-        32 => run_with_word_size::<u32>(cli, index_file_reader),
-        64 => run_with_word_size::<u64>(cli, index_file_reader),
+    match index_header.word_size {
+        8 => run_with_word_size::<u8>(cli, index_header, index_file_reader),
+        16 => run_with_word_size::<u16>(cli, index_header, index_file_reader),
+        32 => run_with_word_size::<u32>(cli, index_header, index_file_reader),
+        64 => run_with_word_size::<u64>(cli, index_header, index_file_reader),
         _ => anyhow::bail!(
             "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
-            word_size
+            index_header.word_size
         ),
     }
 }
 
-fn run_with_word_size<IndexType: GraphIndexInteger + FromStr>(
+fn run_with_word_size<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
+    cli: Cli,
+    index_header: IndexHeader,
+    mut index_file_reader: impl BufRead,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    index_header.check_graph(&graph, cli.index_in.as_deref().unwrap())?;
+
+    info!("Reading index from file {:?}", cli.index_in);
+    let spqr_decomposition = SPQRDecomposition::read_binary(&graph, &mut index_file_reader)
+        .with_context(|| {
+            format!(
+                "Failed to read SPQR decomposition from index file: {:?}",
+                cli.index_in
+            )
+        })?;
+    index_header.check_spqr_decomposition(&spqr_decomposition, cli.index_in.as_deref().unwrap())?;
+    let overlay = Overlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
+        .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
+
+    process_queries(
+        &cli,
+        &graph,
+        || {
+            (
+                OverlayDijkstra::new(&overlay),
+                // Used to reconstruct the parts of the paths that were skipped by overlay edges.
+                cli.computes_paths().then(|| GfaDijkstra::new(&graph)),
+            )
+        },
+        |(dijkstra, unpacking_dijkstra), query| {
+            let mut paths = query.execute(dijkstra, unpacking_dijkstra.is_some());
+            if let Some(unpacking_dijkstra) = unpacking_dijkstra {
+                query.paths = query
+                    .targets
+                    .iter()
+                    .map(|target| {
+                        paths
+                            .remove(target)
+                            .map(|path| unpack_overlay_path(unpacking_dijkstra, path))
+                    })
+                    .collect();
+            }
+        },
+    )
+}
+
+fn run_without_index<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
     cli: Cli,
-    index_file_reader: impl BufRead,
 ) -> anyhow::Result<()>
 where
     <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
 {
     info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
-    let graph = read_optionally_compressed_file(&cli.graph_gfa_in, |reader| {
-        BidirectedAdjacencyArray::<IndexType, PlainGfaNodeData, PlainGfaEdgeData>::read_gfa1(reader)
-            .with_context(|| format!("Failed to parse GFA file {:?}", cli.graph_gfa_in))
-    })
-    .with_context(|| format!("Failed to read GFA file: {:?}", cli.graph_gfa_in))?;
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
     info!(
         "Graph has {} nodes and {} edges",
         graph.node_count(),
         graph.edge_count(),
     );
 
+    process_queries(
+        &cli,
+        &graph,
+        || GfaDijkstra::new(&graph),
+        |dijkstra, query| {
+            let mut paths = query.execute(dijkstra, cli.computes_paths());
+            if cli.computes_paths() {
+                query.paths = query
+                    .targets
+                    .iter()
+                    .map(|target| paths.remove(target))
+                    .collect();
+            }
+        },
+    )
+}
+
+/// Reads the queries in batches, executes them with [`execute_queries`] and writes their results.
+///
+/// Only one batch of queries is kept in memory at a time, and the output is flushed after each batch.
+fn process_queries<IndexType: GraphIndexInteger + FromStr + Send + Sync, Worker: Send>(
+    cli: &Cli,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    create_worker: impl Fn() -> Worker,
+    execute_query: impl Fn(&mut Worker, &mut Query<IndexType>) + Sync,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
     info!("Building node name index");
-    let node_name_index: HashMap<_, _> = graph
-        .node_indices()
-        .map(|node_index| (graph.node_name(node_index), node_index))
-        .collect();
-
-    info!("Reading SPQR decomposition from file {:?}", cli.spqr_in);
-    let spqr_decomposition =
-        read_optionally_compressed_file(cli.spqr_in.as_ref().unwrap(), |reader| {
-            SPQRDecomposition::read_plain_spqr(&graph, reader).with_context(|| {
-                format!("Failed to parse SPQR decomposition file {:?}", cli.spqr_in)
-            })
+    let mut query_parser = QueryParser::new(graph);
+    if cli.max_distance_column {
+        query_parser = query_parser.with_max_distance_column();
+    }
+    let max_distance = cli.max_distance.map(|max_distance| {
+        // A maximum distance that does not fit into the word size does not restrict the search.
+        GfaPathLength::from_raw(
+            usize::try_from(max_distance)
+                .ok()
+                .and_then(|max_distance| IndexType::try_from(max_distance).ok())
+                .unwrap_or_else(IndexType::max_value),
+        )
+    });
+
+    info!("Initialising {} query worker(s)", cli.threads);
+    let mut workers = (0..cli.threads).map(|_| create_worker()).collect_vec();
+
+    let mut query_lines = BufReader::new(
+        open_optionally_compressed_file(&cli.query_in)
+            .with_context(|| format!("Failed to open query file {:?}", cli.query_in))?,
+    )
+    .lines();
+    let mut query_writer = create_optionally_compressed_file(&cli.query_out, cli.compression_level)
+        .with_context(|| format!("Failed to create query output file {:?}", cli.query_out))?;
+    let mut gaf_writer = cli
+        .query_gaf_out
+        .as_ref()
+        .map(|query_gaf_out| {
+            create_optionally_compressed_file(query_gaf_out, cli.compression_level)
+                .with_context(|| format!("Failed to create GAF output file {:?}", query_gaf_out))
+        })
+        .transpose()?;
+    let mut invalid_query_writer = cli
+        .invalid_query_out
+        .as_ref()
+        .map(|invalid_query_out| {
+            create_optionally_compressed_file(invalid_query_out, cli.compression_level)
+                .with_context(|| {
+                    format!(
+                        "Failed to create invalid query output file {:?}",
+                        invalid_query_out
+                    )
+                })
         })
-        .with_context(|| format!("Failed to read SPQR file: {:?}", cli.spqr_in))?;
+        .transpose()?;
 
-    info!("Reading index from file {:?}", cli.index_in);
-    let overlay =
-        SPQRDecompositionOverlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
-            .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
-
-    info!("Reading queries from file {:?}", cli.query_in);
-    let mut queries = read_optionally_compressed_file(&cli.query_in, |reader| {
-        let mut queries = Vec::new();
-        for line in reader.lines() {
+    info!(
+        "Executing queries from file {:?} and writing results to file {:?}",
+        cli.query_in, cli.query_out
+    );
+    let progress_bar = ProgressBar::no_length().with_message("Executing queries");
+    let mut queries = Vec::with_capacity(QUERY_BATCH_SIZE);
+    let mut query_line_numbers = Vec::with_capacity(QUERY_BATCH_SIZE);
+    let mut line_count = 0;
+    let mut query_count = 0;
+    let mut invalid_query_count = 0;
+    let mut execution_time = Duration::ZERO;
+
+    loop {
+        queries.clear();
+        query_line_numbers.clear();
+        let batch_start_line_count = line_count;
+
+        for line in query_lines.by_ref().take(QUERY_BATCH_SIZE) {
             let line = line.with_context(|| {
                 format!("Failed to read line from query file: {:?}", cli.query_in)
             })?;
+            line_count += 1;
 
-            let mut source = None;
-            let mut targets = Vec::new();
-
-            let columns = line.trim().split('\t').collect_vec();
-            for column in columns.chunks(3) {
-                if column.len() != 3 {
-                    anyhow::bail!(
-                        "Invalid query line in file {:?}: expected a number of columns that is divisible by 3, got {}",
-                        cli.query_in,
-                        columns.len()
-                    );
+            match query_parser.parse_query(&line) {
+                Ok(mut query) => {
+                    query.max_distance = query.max_distance.or(max_distance);
+                    queries.push(query);
+                    query_line_numbers.push(line_count);
                 }
+                Err(error) => {
+                    invalid_query_count += 1;
+                    // Tabs would break the columns of the invalid query output file.
+                    let message = format!("{error:#}").replace('\t', " ");
+                    let error = error.context(format!(
+                        "Invalid query in file {:?} at line {}",
+                        cli.query_in, line_count
+                    ));
 
-                let node_name = column[0];
-                let forward = match column[1] {
-                    "+" => true,
-                    "-" => false,
-                    _ => anyhow::bail!(
-                        "Invalid query line in file {:?}: expected orientation to be either '+' or '-', got '{}'",
-                        cli.query_in,
-                        column[1]
-                    ),
-                };
-                let offset = column[2].parse::<IndexType>().map(GfaNodeOffset::from_raw).with_context(|| {
-                    format!(
-                        "Invalid query line in file {:?}: failed to parse offset '{}'",
-                        cli.query_in,
-                        column[2],
-                    )
-                })?;
-
-                let location = GfaLocation::new(DirectedNodeIndex::from_bidirected(node_name_index[node_name], forward), offset);
-                if source.is_none() {
-                    source = Some(location);
-                } else {
-                    targets.push(location);
+                    match cli.on_invalid_query {
+                        InvalidQueryPolicy::Error => return Err(error),
+                        InvalidQueryPolicy::Skip => {}
+                        InvalidQueryPolicy::Report => {
+                            warn!("{error:#}");
+                            if let Some(invalid_query_writer) = &mut invalid_query_writer {
+                                writeln!(invalid_query_writer, "{line_count}\t{message}\t{line}")
+                                    .with_context(|| {
+                                    format!(
+                                        "Failed to write invalid query to file: {:?}",
+                                        cli.invalid_query_out
+                                    )
+                                })?;
+                            }
+                        }
+                    }
                 }
             }
+        }
+        if line_count == batch_start_line_count {
+            break;
+        }
 
-            if source.is_none() || targets.is_empty() {
-                anyhow::bail!(
-                    "Invalid query line in file {:?}: expected at least one source and one target location, got line '{}'",
-                    cli.query_in,
-                    line
-                );
-            }
+        let start_time = Instant::now();
+        execute_queries(
+            &mut workers,
+            &mut queries,
+            QUERY_CHUNK_SIZE,
+            &|worker: &mut Worker, query: &mut Query<IndexType>| {
+                if let Some(k) = cli.k_shortest {
+                    query.execute_k_shortest(graph, k.try_into().unwrap());
+                } else {
+                    execute_query(worker, query);
+                }
+            },
+        );
+        execution_time += start_time.elapsed();
+        progress_bar.inc(queries.len().try_into().unwrap());
 
-            queries.push(Query { source: source.unwrap(), targets, distances: Vec::new() });
+        write_query_results(&mut query_writer, graph, cli.query_paths, &queries)
+            .and_then(|()| Ok(query_writer.flush()?))
+            .with_context(|| {
+                format!("Failed to write query results to file: {:?}", cli.query_out)
+            })?;
+        if let Some(gaf_writer) = &mut gaf_writer {
+            write_query_gaf(gaf_writer, graph, &query_line_numbers, &queries)
+                .and_then(|()| Ok(gaf_writer.flush()?))
+                .with_context(|| {
+                    format!(
+                        "Failed to write query paths to GAF file: {:?}",
+                        cli.query_gaf_out
+                    )
+                })?;
         }
 
-        Ok(queries)
-    })
-    .with_context(|| format!("Failed to read query file: {:?}", cli.query_in))?;
-
-    info!("Initialising overlay Dijkstra data structures");
-    let mut dijkstra = OverlayDijkstra::new(&overlay);
+        if let Some(invalid_query_writer) = &mut invalid_query_writer {
+            invalid_query_writer.flush().with_context(|| {
+                format!(
+                    "Failed to write invalid queries to file: {:?}",
+                    cli.invalid_query_out
+                )
+            })?;
+        }
 
-    info!("Executing queries");
-    let progress_bar =
-        ProgressBar::new(queries.len().try_into().unwrap()).with_message("Executing queries");
-    let start_time = Instant::now();
+        query_count += queries.len();
+    }
 
-    for query in &mut queries {
-        let paths = if query.targets.len() == 1 {
-            dijkstra.shortest_paths(
-                query.source,
-                &SingleGfaLocationIndex::new_target(query.targets[0]),
+    progress_bar.finish_and_clear();
+    query_writer
+        .finish()
+        .with_context(|| format!("Failed to write query results to file: {:?}", cli.query_out))?;
+    if let Some(gaf_writer) = gaf_writer {
+        gaf_writer.finish().with_context(|| {
+            format!(
+                "Failed to write query paths to GAF file: {:?}",
+                cli.query_gaf_out
             )
-        } else {
-            dijkstra.shortest_paths(
-                query.source,
-                &MultiGfaLocationIndex::new_targets(&graph, query.targets.iter().copied()),
+        })?;
+    }
+    if let Some(invalid_query_writer) = invalid_query_writer {
+        invalid_query_writer.finish().with_context(|| {
+            format!(
+                "Failed to write invalid queries to file: {:?}",
+                cli.invalid_query_out
             )
-        };
-        query.distances = query
-            .targets
-            .iter()
-            .map(|&target| paths.get(&target).map(|path| path.length()).into())
-            .collect();
-
-        progress_bar.inc(1);
+        })?;
     }
 
-    let end_time = Instant::now();
-    progress_bar.finish_and_clear();
+    if invalid_query_count > 0 {
+        warn!(
+            "Skipped {} invalid queries out of {} query lines",
+            invalid_query_count, line_count
+        );
+    }
 
     info!(
         "Finished executing {} queries in {:.2?} ({:.0}µs per query)",
-        queries.len(),
-        end_time - start_time,
-        (end_time - start_time).as_secs_f64() / queries.len() as f64 * 1_000_000.0
+        query_count,
+        execution_time,
+        execution_time.as_secs_f64() / query_count as f64 * 1_000_000.0
     );
 
-    info!("Writing query results to file {:?}", cli.query_out);
-    write_optionally_compressed_file(&cli.query_out, |writer| {
-        for query in &queries {
-            write!(
-                writer,
-                "{}\t{}\t{}",
-                graph.node_name(query.source.node().into_bidirected()),
-                query.source.offset(),
-                if query.source.node().is_forward() {
-                    "+"
-                } else {
-                    "-"
-                },
-            )?;
-
-            for (target, distance) in query.targets.iter().zip(&query.distances) {
-                write!(
-                    writer,
-                    "\t{}\t{}\t{}\t{}",
-                    graph.node_name(target.node().into_bidirected()),
-                    target.offset(),
-                    if target.node().is_forward() { "+" } else { "-" },
-                    distance
-                        .into_option()
-                        .as_ref()
-                        .map(ToString::to_string)
-                        .unwrap_or_else(|| "None".to_string()),
-                )?;
-            }
-
-            writeln!(writer)?;
-        }
-        Ok(())
-    })
-    .with_context(|| format!("Failed to write query results to file: {:?}", cli.query_out))?;
-
     Ok(())
 }
 
-fn run_without_index<IndexType: GraphIndexInteger + FromStr>(cli: Cli) -> anyhow::Result<()>
+impl<'graph, IndexType: GraphIndexInteger + FromStr> QueryParser<'graph, IndexType>
 where
     <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
 {
-    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
-    let graph = read_optionally_compressed_file(&cli.graph_gfa_in, |reader| {
-        BidirectedAdjacencyArray::<IndexType, PlainGfaNodeData, PlainGfaEdgeData>::read_gfa1(reader)
-            .with_context(|| format!("Failed to parse GFA file {:?}", cli.graph_gfa_in))
-    })
-    .with_context(|| format!("Failed to read GFA file: {:?}", cli.graph_gfa_in))?;
-    info!(
-        "Graph has {} nodes and {} edges",
-        graph.node_count(),
-        graph.edge_count(),
-    );
-
-    info!("Building node name index");
-    let node_name_index: HashMap<_, _> = graph
-        .node_indices()
-        .map(|node_index| (graph.node_name(node_index), node_index))
-        .collect();
-
-    info!("Reading queries from file {:?}", cli.query_in);
-    let mut queries = read_optionally_compressed_file(&cli.query_in, |reader| {
-        let mut queries = Vec::new();
-        for line in reader.lines() {
-            let line = line.with_context(|| {
-                format!("Failed to read line from query file: {:?}", cli.query_in)
-            })?;
+    pub(crate) fn new(
+        graph: &'graph BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    ) -> Self {
+        Self {
+            graph,
+            node_name_index: graph
+                .node_indices()
+                .map(|node_index| (graph.node_name(node_index), node_index))
+                .collect(),
+            max_distance_column: false,
+        }
+    }
 
-            let mut source = None;
-            let mut targets = Vec::new();
-
-            let columns = line.trim().split('\t').collect_vec();
-            for column in columns.chunks(3) {
-                if column.len() != 3 {
-                    anyhow::bail!(
-                        "Invalid query line in file {:?}: expected a number of columns that is divisible by 3, got {}",
-                        cli.query_in,
-                        columns.len()
-                    );
-                }
+    /// Expect the last column of each query line to be the maximum distance of the query.
+    pub(crate) fn with_max_distance_column(mut self) -> Self {
+        self.max_distance_column = true;
+        self
+    }
 
-                let node_name = column[0];
-                let forward = match column[1] {
-                    "+" => true,
-                    "-" => false,
-                    _ => anyhow::bail!(
-                        "Invalid query line in file {:?}: expected orientation to be either '+' or '-', got '{}'",
-                        cli.query_in,
-                        column[1]
-                    ),
-                };
-                let offset = column[2].parse::<IndexType>().map(GfaNodeOffset::from_raw).with_context(|| {
-                    format!(
-                        "Invalid query line in file {:?}: failed to parse offset '{}'",
-                        cli.query_in,
-                        column[2],
-                    )
-                })?;
+    /// Parses a query line in the format of the query input file.
+    /// Errors name the one-based column they occurred in.
+    pub(crate) fn parse_query(&self, line: &str) -> anyhow::Result<Query<IndexType>> {
+        let mut source = None;
+        let mut targets = Vec::new();
 
-                let location = GfaLocation::new(DirectedNodeIndex::from_bidirected(node_name_index[node_name], forward), offset);
-                if source.is_none() {
-                    source = Some(location);
-                } else {
-                    targets.push(location);
-                }
-            }
+        let mut columns = line.trim().split('\t').collect_vec();
+        let max_distance = if self.max_distance_column {
+            // Splitting always yields at least one column.
+            let max_distance = columns.pop().unwrap();
+            Some(
+                max_distance
+                    .parse::<IndexType>()
+                    .map(GfaPathLength::from_raw)
+                    .with_context(|| format!("failed to parse maximum distance '{}'", max_distance))
+                    .with_context(|| format!("column {}", columns.len() + 1))?,
+            )
+        } else {
+            None
+        };
 
-            if source.is_none() || targets.is_empty() {
+        for (chunk_index, column) in columns.chunks(3).enumerate() {
+            if column.len() != 3 {
                 anyhow::bail!(
-                    "Invalid query line in file {:?}: expected at least one source and one target location, got line '{}'",
-                    cli.query_in,
-                    line
+                    "expected a number of columns that is divisible by 3, got {}",
+                    columns.len()
                 );
             }
 
-            queries.push(Query { source: source.unwrap(), targets, distances: Vec::new() });
+            let location = self
+                .parse_location_fields(column[0], column[1], column[2])
+                .map_err(|(field_index, error)| {
+                    error.context(format!("column {}", chunk_index * 3 + field_index + 1))
+                })?;
+            if source.is_none() {
+                source = Some(location);
+            } else {
+                targets.push(location);
+            }
         }
 
-        Ok(queries)
-    })
-    .with_context(|| format!("Failed to read query file: {:?}", cli.query_in))?;
+        if source.is_none() || targets.is_empty() {
+            anyhow::bail!(
+                "expected at least one source and one target location, got line '{}'",
+                line
+            );
+        }
 
-    info!("Initialising Dijkstra data structures");
-    let mut dijkstra = GfaDijkstra::new(&graph);
+        let mut query = Query::new(source.unwrap(), targets);
+        query.max_distance = max_distance;
+        Ok(query)
+    }
 
-    info!("Executing queries");
-    let progress_bar =
-        ProgressBar::new(queries.len().try_into().unwrap()).with_message("Executing queries");
-    let start_time = Instant::now();
+    /// Parses a location given as node name, orientation (`+` or `-`) and offset.
+    pub(crate) fn parse_location(
+        &self,
+        node_name: &str,
+        orientation: &str,
+        offset: &str,
+    ) -> anyhow::Result<GfaLocation<IndexType>> {
+        self.parse_location_fields(node_name, orientation, offset)
+            .map_err(|(field_index, error)| {
+                error.context(["node", "orientation", "offset"][field_index])
+            })
+    }
 
-    for query in &mut queries {
-        let paths = if query.targets.len() == 1 {
-            dijkstra.shortest_paths(
-                query.source,
-                &SingleGfaLocationIndex::new_target(query.targets[0]),
-            )
-        } else {
-            dijkstra.shortest_paths(
-                query.source,
-                &MultiGfaLocationIndex::new_targets(&graph, query.targets.iter().copied()),
-            )
+    /// Like [`Self::parse_location`], but on error also returns the index of the field that is invalid.
+    fn parse_location_fields(
+        &self,
+        node_name: &str,
+        orientation: &str,
+        offset: &str,
+    ) -> Result<GfaLocation<IndexType>, (usize, anyhow::Error)> {
+        let node = *self
+            .node_name_index
+            .get(node_name)
+            .ok_or_else(|| (0, anyhow::anyhow!("unknown node name '{}'", node_name)))?;
+        let forward = match orientation {
+            "+" => true,
+            "-" => false,
+            _ => {
+                return Err((
+                    1,
+                    anyhow::anyhow!(
+                        "expected orientation to be either '+' or '-', got '{}'",
+                        orientation
+                    ),
+                ));
+            }
         };
-        query.distances = query
-            .targets
-            .iter()
-            .map(|&target| paths.get(&target).map(|path| path.length()).into())
-            .collect();
+        let offset = offset
+            .parse::<IndexType>()
+            .map(GfaNodeOffset::from_raw)
+            .with_context(|| format!("failed to parse offset '{}'", offset))
+            .map_err(|error| (2, error))?;
 
-        progress_bar.inc(1);
+        let node_length = GfaNodeDataExt::<IndexType>::len(self.graph.node_data(node));
+        if offset.into_length() > node_length {
+            return Err((
+                2,
+                anyhow::anyhow!(
+                    "offset {} is larger than the length {} of node '{}'",
+                    offset,
+                    node_length,
+                    node_name
+                ),
+            ));
+        }
+
+        Ok(GfaLocation::new(
+            DirectedNodeIndex::from_bidirected(node, forward),
+            offset,
+        ))
     }
+}
 
-    let end_time = Instant::now();
-    progress_bar.finish_and_clear();
+/// Executes the queries with one thread per worker, storing the results in the queries themselves.
+/// Threads take `chunk_size` queries at once.
+pub(crate) fn execute_queries<IndexType: Send, Worker: Send>(
+    workers: &mut [Worker],
+    queries: &mut [Query<IndexType>],
+    chunk_size: usize,
+    execute_query: &(impl Fn(&mut Worker, &mut Query<IndexType>) + Sync),
+) {
+    // Threads take chunks of queries from a shared iterator, so the results stay in input order.
+    let chunks = Mutex::new(queries.chunks_mut(chunk_size));
+    thread::scope(|scope| {
+        for worker in workers {
+            let chunks = &chunks;
+            scope.spawn(move || {
+                loop {
+                    let next_chunk = chunks.lock().unwrap().next();
+                    let Some(chunk) = next_chunk else {
+                        break;
+                    };
 
-    info!(
-        "Finished executing {} queries in {:.2?} ({:.0}µs per query)",
-        queries.len(),
-        end_time - start_time,
-        (end_time - start_time).as_secs_f64() / queries.len() as f64 * 1_000_000.0
-    );
+                    for query in chunk {
+                        execute_query(worker, query);
+                    }
+                }
+            });
+        }
+    });
+}
 
-    info!("Writing query results to file {:?}", cli.query_out);
-    write_optionally_compressed_file(&cli.query_out, |writer| {
-        for query in &queries {
+pub(crate) fn write_query_results<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    query_paths: bool,
+    queries: &[Query<IndexType>],
+) -> anyhow::Result<()> {
+    for query in queries {
+        write!(
+            writer,
+            "{}\t{}\t{}",
+            graph.node_name(query.source.node().into_bidirected()),
+            query.source.offset(),
+            if query.source.node().is_forward() {
+                "+"
+            } else {
+                "-"
+            },
+        )?;
+
+        for (index, (target, distance)) in query.targets.iter().zip(&query.distances).enumerate() {
             write!(
                 writer,
-                "{}\t{}\t{}",
-                graph.node_name(query.source.node().into_bidirected()),
-                query.source.offset(),
-                if query.source.node().is_forward() {
-                    "+"
-                } else {
-                    "-"
+                "\t{}\t{}\t{}\t{}",
+                graph.node_name(target.node().into_bidirected()),
+                target.offset(),
+                if target.node().is_forward() { "+" } else { "-" },
+                match (distance.into_option(), query.max_distance) {
+                    (Some(_), _) if !query.walks.is_empty() => query.walks[index]
+                        .iter()
+                        .map(|walk| walk.length().to_string())
+                        .join(","),
+                    (Some(distance), _) => distance.to_string(),
+                    (None, Some(max_distance)) if query.cut_off[index] => {
+                        format!(">{max_distance}")
+                    }
+                    (None, _) => "None".to_string(),
                 },
             )?;
 
-            for (target, distance) in query.targets.iter().zip(&query.distances) {
-                write!(
-                    writer,
-                    "\t{}\t{}\t{}\t{}",
-                    graph.node_name(target.node().into_bidirected()),
-                    target.offset(),
-                    if target.node().is_forward() { "+" } else { "-" },
-                    distance
-                        .into_option()
-                        .as_ref()
-                        .map(ToString::to_string)
-                        .unwrap_or_else(|| "None".to_string()),
-                )?;
+            if query_paths {
+                let paths = query.target_paths(index);
+                if paths.is_empty() {
+                    write!(writer, "\tNone\tNone\tNone")?;
+                } else {
+                    write!(
+                        writer,
+                        "\t{}\t{}\t{}",
+                        paths.iter().map(|path| format_walk(graph, path)).join(";"),
+                        paths
+                            .iter()
+                            .map(|path| path.iter().next().unwrap().offset())
+                            .join(";"),
+                        paths
+                            .iter()
+                            .map(|path| path.iter().last().unwrap().limit())
+                            .join(";"),
+                    )?;
+                }
             }
+        }
+
+        writeln!(writer)?;
+    }
 
-            writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes the paths of the queries as GAF records.
+/// The records are named after the one-based line numbers of the queries in the query file
+/// and the one-based numbers of their targets in the query line.
+/// If the k shortest walks were computed, the one-based rank of the walk is appended to the name.
+fn write_query_gaf<IndexType: GraphIndexInteger>(
+    writer: &mut impl Write,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    query_line_numbers: &[usize],
+    queries: &[Query<IndexType>],
+) -> anyhow::Result<()> {
+    for (line_number, query) in query_line_numbers.iter().zip(queries) {
+        for (target_index, rank, gfa_path) in (0..query.targets.len()).flat_map(|target_index| {
+            query
+                .target_paths(target_index)
+                .into_iter()
+                .enumerate()
+                .map(move |(rank, gfa_path)| (target_index, rank, gfa_path))
+        }) {
+            let target_number = target_index + 1;
+            let name = if query.walks.is_empty() {
+                format!("{line_number}_{target_number}")
+            } else {
+                format!("{line_number}_{target_number}_{}", rank + 1)
+            };
+
+            // Links may overlap, so the length of the path is computed from the distance rather than from the node lengths.
+            let last_element = gfa_path.iter().last().unwrap();
+            let last_node_length = graph
+                .node_data(last_element.node().into_bidirected())
+                .length();
+            let distance = gfa_path.length().into_usize();
+            let path_start = gfa_path.iter().next().unwrap().offset().into_usize();
+            let path_end = path_start + distance;
+            let path_length = path_end + last_node_length - last_element.limit().into_usize();
+
+            // The distance takes the role of the query sequence, which matches the path perfectly.
+            writeln!(
+                writer,
+                "{name}\t{distance}\t0\t{distance}\t+\t{}\t{path_length}\t{path_start}\t{path_end}\t{distance}\t{distance}\t255",
+                gfa_path
+                    .iter()
+                    .map(|element| format!(
+                        "{}{}",
+                        if element.node().is_forward() {
+                            ">"
+                        } else {
+                            "<"
+                        },
+                        graph.node_name(element.node().into_bidirected()),
+                    ))
+                    .join(""),
+            )?;
         }
-        Ok(())
-    })
-    .with_context(|| format!("Failed to write query results to file: {:?}", cli.query_out))?;
+    }
 
     Ok(())
 }
+
+/// Formats the nodes of a path as a comma-separated oriented walk, e.g. `a+,c+,d-`.
+fn format_walk<IndexType: GraphIndexInteger>(
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    path: &GfaPath<IndexType>,
+) -> String {
+    path.iter()
+        .map(|element| {
+            format!(
+                "{}{}",
+                graph.node_name(element.node().into_bidirected()),
+                if element.node().is_forward() {
+                    "+"
+                } else {
+                    "-"
+                },
+            )
+        })
+        .join(",")
+}
+
+/// Paths returned by [`OverlayDijkstra`] jump over the nodes that are contracted into overlay edges.
+/// This reconstructs the skipped nodes by running a plain Dijkstra between the endpoints of each jump.
+fn unpack_overlay_path<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    dijkstra: &mut GfaDijkstra<'_, IndexType, NodeData, EdgeData>,
+    path: GfaPath<IndexType>,
+) -> GfaPath<IndexType> {
+    let graph = dijkstra.graph();
+    let mut unpacked_path = Vec::new();
+
+    // With overlapping links, a direct edge between the endpoints of a jump is not necessarily the shortest connection,
+    // so every jump is searched for.
+    for (element, next_element) in path.iter().tuple_windows() {
+        unpacked_path.push(element);
+        let (_, inner_nodes) = dijkstra
+            .shortest_walk(element.node(), next_element.node())
+            .expect("overlay edges correspond to walks in the graph");
+        unpacked_path.extend(inner_nodes.into_iter().map(|node| {
+            PathElement::new(
+                node,
+                GfaNodeOffset::from_usize(0),
+                GfaNodeDataExt::<IndexType>::len(graph.node_data(node.into_bidirected()))
+                    .into_offset(),
+            )
+        }));
+    }
+    unpacked_path.extend(path.iter().last());
+
+    GfaPath::new(unpacked_path, path.length())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bidirected_adjacency_array::graph::BidirectedAdjacencyArray;
+
+    use super::*;
+    use crate::gfa::read_gfa;
+
+    fn graph() -> BidirectedAdjacencyArray<u32, GfaSegmentData, PlainGfaEdgeData> {
+        read_gfa(b"S\ta\tACGT\nS\tb\tAC\nL\ta\t+\tb\t-\t0M\n".as_slice()).unwrap()
+    }
+
+    fn location(node: u32, forward: bool, offset: u32) -> GfaLocation<u32> {
+        GfaLocation::new(
+            DirectedNodeIndex::from_bidirected(NodeIndex::from_raw(node), forward),
+            GfaNodeOffset::from_raw(offset),
+        )
+    }
+
+    #[test]
+    fn parse_query() {
+        let graph = graph();
+        let query = QueryParser::new(&graph)
+            .parse_query("a\t+\t1\tb\t-\t2\ta\t-\t4")
+            .unwrap();
+        assert_eq!(query.source(), location(0, true, 1));
+        assert_eq!(
+            query.targets(),
+            [location(1, false, 2), location(0, false, 4)]
+        );
+        assert_eq!(query.max_distance, None);
+    }
+
+    #[test]
+    fn parse_query_with_max_distance_column() {
+        let graph = graph();
+        let query = QueryParser::new(&graph)
+            .with_max_distance_column()
+            .parse_query("a\t+\t0\tb\t+\t0\t7")
+            .unwrap();
+        assert_eq!(query.targets(), [location(1, true, 0)]);
+        assert_eq!(query.max_distance, Some(GfaPathLength::from_raw(7)));
+    }
+
+    #[test]
+    fn parse_query_errors_name_the_column() {
+        let graph = graph();
+        let query_parser = QueryParser::new(&graph);
+        let error = |line| format!("{:#}", query_parser.parse_query(line).err().unwrap());
+
+        assert_eq!(error("a\t+\t0\tc\t+\t0"), "column 4: unknown node name 'c'",);
+        assert_eq!(
+            error("a\t*\t0\tb\t+\t0"),
+            "column 2: expected orientation to be either '+' or '-', got '*'",
+        );
+        assert_eq!(
+            error("a\t+\t0\tb\t+\t3"),
+            "column 6: offset 3 is larger than the length 2 of node 'b'",
+        );
+        assert!(error("a\t+\t0\tb\t+\tx").starts_with("column 6: failed to parse offset 'x'"));
+        assert_eq!(
+            error("a\t+\t0\tb\t+"),
+            "expected a number of columns that is divisible by 3, got 5",
+        );
+        assert_eq!(
+            error("a\t+\t0"),
+            "expected at least one source and one target location, got line 'a\t+\t0'",
+        );
+    }
+
+    #[test]
+    fn parse_location_errors_name_the_field() {
+        let graph = graph();
+        let query_parser = QueryParser::new(&graph);
+        assert_eq!(
+            query_parser.parse_location("b", "-", "2").unwrap(),
+            location(1, false, 2),
+        );
+        assert_eq!(
+            format!(
+                "{:#}",
+                query_parser.parse_location("b", "+", "5").unwrap_err()
+            ),
+            "offset: offset 5 is larger than the length 2 of node 'b'",
+        );
+    }
+
+    #[test]
+    fn invalid_queries_and_gaf_records_use_line_numbers() {
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-query-lines-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_string();
+        fs::write(
+            path("graph.gfa"),
+            "S\ta\tACGT\nS\tb\tAC\nL\ta\t+\tb\t-\t0M\n",
+        )
+        .unwrap();
+        fs::write(
+            path("queries"),
+            "a\t+\t1\tb\t-\t2\na\t+\t0\tc\t+\t0\na\t+\t0\tb\t+\t0\tb\t-\t1\n",
+        )
+        .unwrap();
+        let args = |on_invalid_query| {
+            [
+                "query",
+                "--graph-gfa-in",
+                &path("graph.gfa"),
+                "--query-in",
+                &path("queries"),
+                "--query-out",
+                &path("results"),
+                "--query-gaf-out",
+                &path("paths.gaf"),
+                "--invalid-query-out",
+                &path("invalid"),
+                "--on-invalid-query",
+                on_invalid_query,
+            ]
+            .map(str::to_string)
+        };
+
+        assert_eq!(
+            run(Cli::parse_from(args("skip")))
+                .err()
+                .unwrap()
+                .to_string(),
+            "--invalid-query-out can only be used with --on-invalid-query report",
+        );
+
+        run(Cli::parse_from(args("report"))).unwrap();
+        assert_eq!(
+            fs::read_to_string(path("invalid")).unwrap(),
+            "2\tcolumn 4: unknown node name 'c'\ta\t+\t0\tc\t+\t0\n",
+        );
+        let gaf = fs::read_to_string(path("paths.gaf")).unwrap();
+        let names: Vec<_> = gaf
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(names, ["1_1", "3_2"]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unreachable_targets_are_not_farther_than_the_maximum_distance() {
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-query-max-distance-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_string();
+        fs::write(
+            path("graph.gfa"),
+            "S\ta\tACGT\nS\tb\tAC\nS\tc\tA\nL\ta\t+\tb\t-\t0M\nL\tc\t+\ta\t-\t0M\n",
+        )
+        .unwrap();
+        // The node c+ is unreachable from a+, while b- and c- are 5 and 4 away.
+        fs::write(
+            path("queries"),
+            "a\t+\t0\tb\t-\t1\tc\t+\t0\t10\na\t+\t0\tb\t-\t1\tc\t-\t0\t2\n",
+        )
+        .unwrap();
+        crate::index::run(crate::index::Cli::parse_from([
+            "index",
+            "--graph-gfa-in",
+            &path("graph.gfa"),
+            "--index-out",
+            &path("index"),
+        ]))
+        .unwrap();
+
+        let query = |extra_args: &[&str]| {
+            let args = [
+                "query",
+                "--graph-gfa-in",
+                &path("graph.gfa"),
+                "--query-in",
+                &path("queries"),
+                "--query-out",
+                &path("results"),
+                "--max-distance-column",
+            ];
+            run(Cli::parse_from(args.iter().chain(extra_args))).unwrap();
+            fs::read_to_string(path("results")).unwrap()
+        };
+        let expected = "a\t0\t+\tb\t1\t-\t5\tc\t0\t+\tNone\n\
+                        a\t0\t+\tb\t1\t-\t>2\tc\t0\t-\t>2\n";
+        assert_eq!(query(&[]), expected);
+        assert_eq!(query(&["--index-in", &path("index")]), expected);
+        assert_eq!(query(&["--k-shortest", "2"]), expected);
+
+        assert!(
+            Cli::try_parse_from([
+                "query",
+                "--graph-gfa-in",
+                "graph.gfa",
+                "--index-in",
+                "index",
+                "--query-in",
+                "queries",
+                "--query-out",
+                "results",
+                "--k-shortest",
+                "2",
+            ])
+            .is_err()
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn longer_walks_can_pass_the_source_before_reaching_the_target() {
+        const GRAPH_GFA: &str = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_files/overlap_before_source.gfa"
+        );
+        let directory = std::env::temp_dir().join(format!(
+            "biopath-test-query-overlap-before-source-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_string();
+        // The direct link from s+ to t+ reaches the start of t+ at 2, before the source at 9.
+        // The walk over u+ reaches the start of t+ at 6, so it passes the source before t+ at 5.
+        fs::write(path("queries"), "s\t+\t9\tt\t+\t5\tt\t+\t0\tu\t+\t5\n").unwrap();
+        crate::index::run(crate::index::Cli::parse_from([
+            "index",
+            "--graph-gfa-in",
+            GRAPH_GFA,
+            "--index-out",
+            &path("index"),
+        ]))
+        .unwrap();
+
+        let query = |extra_args: &[&str]| {
+            let args = [
+                "query",
+                "--graph-gfa-in",
+                GRAPH_GFA,
+                "--query-in",
+                &path("queries"),
+                "--query-out",
+                &path("results"),
+            ];
+            run(Cli::parse_from(args.iter().chain(extra_args))).unwrap();
+            fs::read_to_string(path("results")).unwrap()
+        };
+        let expected = "s\t9\t+\tt\t5\t+\t2\tt\t0\t+\tNone\tu\t5\t+\t0\n";
+        assert_eq!(query(&[]), expected);
+        assert_eq!(query(&["--index-in", &path("index")]), expected);
+        assert_eq!(query(&["--k-shortest", "1"]), expected);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// The example query for tiny1 runs on the variant of the graph with `LN` tags,
+    /// while the variant without sequences and lengths is rejected.
+    #[test]
+    fn tiny1_example_query_runs_with_segment_lengths() {
+        const TINY1_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1.gfa");
+        const TINY1_LN_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1_ln.gfa");
+        const TINY1_QUERY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1.query");
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-query-tiny1-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let query_out = directory.join("results").to_str().unwrap().to_string();
+
+        let query = |graph_gfa_in| {
+            run(Cli::parse_from([
+                "query",
+                "--graph-gfa-in",
+                graph_gfa_in,
+                "--query-in",
+                TINY1_QUERY,
+                "--query-out",
+                &query_out,
+            ]))
+        };
+        query(TINY1_LN_GFA).unwrap();
+        assert_eq!(
+            fs::read_to_string(&query_out).unwrap(),
+            "a\t0\t+\ta\t1\t+\t1\td\t0\t-\tNone\n\
+             c\t0\t+\ta\t1\t+\tNone\tg\t0\t-\tNone\n\
+             k\t0\t+\tb\t1\t+\tNone\tb\t0\t-\tNone\n\
+             h\t0\t+\ta\t1\t+\tNone\tg\t0\t-\t1\n",
+        );
+
+        let error = query(TINY1_GFA).err().unwrap();
+        assert!(
+            error.chain().any(|cause| cause.to_string()
+                == "Segment a in S line 2 has neither a sequence nor an LN tag, so its length is unknown"),
+            "{error:?}"
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,456 @@
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+use std::{
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray, index::GraphIndexInteger, io::gfa1::PlainGfaEdgeData,
+};
+use clap::{ArgGroup, Parser};
+use itertools::Itertools;
+use log::{LevelFilter, info, warn};
+use serde::{Deserialize, Serialize};
+use spqr_tree::decomposition::SPQRDecomposition;
+
+use crate::{
+    dijkstra::OverlayDijkstra,
+    gfa::{GfaSegmentData, read_gfa_file},
+    index::{IndexHeader, read_index_header},
+    io_util::ensure_stdio_used_at_most_once,
+    overlay::Overlay,
+    query::{Query, QueryParser, write_query_results},
+};
+
+#[derive(Parser)]
+#[cfg_attr(
+    unix,
+    clap(group(ArgGroup::new("listen").required(true).args(["unix_socket", "tcp_port"])))
+)]
+#[cfg_attr(
+    not(unix),
+    clap(group(ArgGroup::new("listen").required(true).args(["tcp_port"])))
+)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph to index.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The index file.
+    #[clap(long)]
+    index_in: PathBuf,
+
+    /// Listen for connections on a Unix domain socket at this path.
+    #[cfg(unix)]
+    #[clap(long)]
+    unix_socket: Option<PathBuf>,
+
+    /// Listen for connections on this TCP port on localhost.
+    #[clap(long)]
+    tcp_port: Option<u16>,
+
+    /// The number of threads to serve connections with.
+    /// Each thread serves one connection at a time, and further connections wait until a thread is free.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+
+    /// Close connections that send no request and accept no response for this many seconds,
+    /// so that idle clients do not keep the threads from serving other connections.
+    /// Zero disables the timeout.
+    #[clap(long, default_value = "60")]
+    idle_timeout: u64,
+}
+
+/// A request in JSON format.
+///
+/// Requests that do not start with `{` are interpreted as lines in the query file format of `biopath query`,
+/// and are answered with a line in its output format.
+#[derive(Debug, Deserialize)]
+struct JsonRequest {
+    source: JsonLocation,
+    targets: Vec<JsonLocation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLocation {
+    node: String,
+    orientation: String,
+    offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum JsonResponse {
+    Distances { distances: Vec<Option<usize>> },
+    Error { error: String },
+}
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once([cli.graph_gfa_in.as_path(), cli.index_in.as_path()], [])?;
+
+    let (index_header, index_file_reader) = read_index_header(&cli.index_in)?;
+
+    match index_header.word_size {
+        8 => run_with_word_size::<u8>(cli, index_header, index_file_reader),
+        16 => run_with_word_size::<u16>(cli, index_header, index_file_reader),
+        32 => run_with_word_size::<u32>(cli, index_header, index_file_reader),
+        64 => run_with_word_size::<u64>(cli, index_header, index_file_reader),
+        _ => anyhow::bail!(
+            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
+            index_header.word_size
+        ),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
+    cli: Cli,
+    index_header: IndexHeader,
+    mut index_file_reader: impl BufRead,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    index_header.check_graph(&graph, &cli.index_in)?;
+
+    info!("Reading index from file {:?}", cli.index_in);
+    let spqr_decomposition = SPQRDecomposition::read_binary(&graph, &mut index_file_reader)
+        .with_context(|| {
+            format!(
+                "Failed to read SPQR decomposition from index file: {:?}",
+                cli.index_in
+            )
+        })?;
+    index_header.check_spqr_decomposition(&spqr_decomposition, &cli.index_in)?;
+    let overlay = Overlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
+        .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
+
+    info!("Building node name index");
+    let query_parser = QueryParser::new(&graph);
+
+    let idle_timeout = (cli.idle_timeout > 0).then(|| Duration::from_secs(cli.idle_timeout));
+    let create_worker = || OverlayDijkstra::new(&overlay);
+    let handle_connection = |dijkstra: &mut OverlayDijkstra<'_, '_, '_, _, _, _>,
+                             reader: &mut dyn Read,
+                             writer: &mut dyn Write| {
+        match serve_connection(
+            BufReader::new(reader),
+            BufWriter::new(writer),
+            dijkstra,
+            &graph,
+            &query_parser,
+        ) {
+            Ok(()) => {}
+            Err(error)
+                if error.downcast_ref::<std::io::Error>().is_some_and(|error| {
+                    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                }) =>
+            {
+                info!(
+                    "Closing connection that was idle for {} seconds",
+                    cli.idle_timeout
+                );
+            }
+            Err(error) => warn!("Closing connection after I/O error: {error:#}"),
+        }
+    };
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    #[cfg(unix)]
+    if let Some(unix_socket) = &cli.unix_socket {
+        let listener = bind_unix_socket(unix_socket)?;
+        let _socket_file = UnixSocketFile(unix_socket);
+        let wake_path = unix_socket.clone();
+        set_shutdown_handler(&shutdown, move || UnixStream::connect(&wake_path).map(drop))?;
+        info!(
+            "Listening on Unix socket {:?} with {} thread(s)",
+            unix_socket, cli.threads
+        );
+        serve_connections(
+            listener.incoming(),
+            cli.threads,
+            &shutdown,
+            &create_worker,
+            &|dijkstra, stream| {
+                if let Err(error) = stream
+                    .set_read_timeout(idle_timeout)
+                    .and_then(|()| stream.set_write_timeout(idle_timeout))
+                {
+                    warn!("Closing connection after failing to set its timeout: {error}");
+                    return;
+                }
+                handle_connection(dijkstra, &mut &stream, &mut &stream)
+            },
+        );
+        return Ok(());
+    }
+
+    let tcp_port = cli.tcp_port.unwrap();
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, tcp_port))
+        .with_context(|| format!("Failed to bind TCP port {}", tcp_port))?;
+    set_shutdown_handler(&shutdown, move || {
+        TcpStream::connect((Ipv4Addr::LOCALHOST, tcp_port)).map(drop)
+    })?;
+    info!(
+        "Listening on TCP port {} with {} thread(s)",
+        tcp_port, cli.threads
+    );
+    serve_connections(
+        listener.incoming(),
+        cli.threads,
+        &shutdown,
+        &create_worker,
+        &|dijkstra, stream| {
+            if let Err(error) = stream
+                .set_read_timeout(idle_timeout)
+                .and_then(|()| stream.set_write_timeout(idle_timeout))
+            {
+                warn!("Closing connection after failing to set its timeout: {error}");
+                return;
+            }
+            handle_connection(dijkstra, &mut &stream, &mut &stream)
+        },
+    );
+    Ok(())
+}
+
+/// Binds the Unix socket, removing a socket file that a previous server left behind.
+///
+/// A socket file that still accepts connections belongs to a running server and is not removed.
+#[cfg(unix)]
+fn bind_unix_socket(unix_socket: &Path) -> anyhow::Result<UnixListener> {
+    if fs::symlink_metadata(unix_socket).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if UnixStream::connect(unix_socket).is_ok() {
+            anyhow::bail!(
+                "Another server is already listening on Unix socket {:?}",
+                unix_socket
+            );
+        }
+        info!("Removing leftover Unix socket {:?}", unix_socket);
+        fs::remove_file(unix_socket)
+            .with_context(|| format!("Failed to remove leftover Unix socket {:?}", unix_socket))?;
+    }
+
+    UnixListener::bind(unix_socket)
+        .with_context(|| format!("Failed to bind Unix socket {:?}", unix_socket))
+}
+
+/// Removes the file of the Unix socket when the server stops.
+#[cfg(unix)]
+struct UnixSocketFile<'path>(&'path Path);
+
+#[cfg(unix)]
+impl Drop for UnixSocketFile<'_> {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(self.0) {
+            warn!("Failed to remove Unix socket {:?}: {error}", self.0);
+        }
+    }
+}
+
+/// Stops accepting connections on SIGINT and SIGTERM.
+///
+/// The listener blocks until the next connection, so the handler wakes it up by connecting with `wake_listener`.
+fn set_shutdown_handler(
+    shutdown: &Arc<AtomicBool>,
+    wake_listener: impl Fn() -> std::io::Result<()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let shutdown = shutdown.clone();
+    ctrlc::set_handler(move || {
+        info!("Shutting down after the open connections are closed");
+        shutdown.store(true, Ordering::SeqCst);
+        if let Err(error) = wake_listener() {
+            warn!("Failed to wake up the listener: {error}");
+        }
+    })
+    .context("Failed to install the shutdown signal handler")
+}
+
+/// Hands the accepted connections to a fixed number of threads, each with its own worker.
+///
+/// While all threads are busy, the listener stops accepting, so further connections wait in its backlog.
+/// Once `shutdown` is set, no further connections are accepted, and the function returns when the open connections are closed.
+fn serve_connections<Stream: Send, Worker>(
+    incoming: impl Iterator<Item = std::io::Result<Stream>>,
+    threads: u16,
+    shutdown: &AtomicBool,
+    create_worker: &(impl Fn() -> Worker + Sync),
+    handle_connection: &(impl Fn(&mut Worker, Stream) + Sync),
+) {
+    // A rendezvous channel, so a connection is only accepted once a thread takes it.
+    let (sender, receiver) = mpsc::sync_channel(0);
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let receiver = &receiver;
+            scope.spawn(move || {
+                let mut worker = create_worker();
+                loop {
+                    let next_stream = receiver.lock().unwrap().recv();
+                    let Ok(stream) = next_stream else {
+                        break;
+                    };
+                    handle_connection(&mut worker, stream);
+                }
+            });
+        }
+
+        for stream in incoming {
+            if shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            match stream {
+                Ok(stream) => sender
+                    .send(stream)
+                    .expect("the threads run until the sender is dropped"),
+                Err(error) => warn!("Failed to accept connection: {error}"),
+            }
+        }
+        drop(sender);
+    });
+}
+
+/// Answers requests line by line until the client closes the connection.
+fn serve_connection<IndexType: GraphIndexInteger + FromStr>(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    dijkstra: &mut OverlayDijkstra<'_, '_, '_, IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    graph: &BidirectedAdjacencyArray<IndexType, GfaSegmentData, PlainGfaEdgeData>,
+    query_parser: &QueryParser<'_, IndexType>,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line.trim_start().starts_with('{') {
+            let response = match parse_json_request(&line, query_parser) {
+                Ok(mut query) => {
+                    query.execute(dijkstra, false);
+                    JsonResponse::Distances {
+                        distances: query
+                            .distances()
+                            .iter()
+                            .map(|distance| distance.into_option().map(|d| d.into_usize()))
+                            .collect(),
+                    }
+                }
+                Err(error) => JsonResponse::Error {
+                    error: format!("{error:#}"),
+                },
+            };
+            serde_json::to_writer(&mut writer, &response)?;
+            writeln!(writer)?;
+        } else {
+            match query_parser.parse_query(&line) {
+                Ok(mut query) => {
+                    query.execute(dijkstra, false);
+                    write_query_results(&mut writer, graph, false, &[query])?;
+                }
+                Err(error) => writeln!(writer, "Error: {error:#}")?,
+            }
+        }
+
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+fn parse_json_request<IndexType: GraphIndexInteger + FromStr>(
+    line: &str,
+    query_parser: &QueryParser<'_, IndexType>,
+) -> anyhow::Result<Query<IndexType>>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    let request: JsonRequest = serde_json::from_str(line).context("invalid JSON request")?;
+    if request.targets.is_empty() {
+        anyhow::bail!("expected at least one target location");
+    }
+
+    let parse_json_location = |location: &JsonLocation| {
+        query_parser.parse_location(
+            &location.node,
+            &location.orientation,
+            &location.offset.to_string(),
+        )
+    };
+
+    Ok(Query::new(
+        parse_json_location(&request.source)?,
+        request
+            .targets
+            .iter()
+            .map(parse_json_location)
+            .try_collect()?,
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::net::UnixListener};
+
+    use super::{UnixSocketFile, bind_unix_socket};
+
+    #[test]
+    fn leftover_unix_sockets_are_replaced() {
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-serve-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let unix_socket = directory.join("biopath.sock");
+
+        // Dropping a listener leaves its socket file behind, like a server that was killed.
+        drop(UnixListener::bind(&unix_socket).unwrap());
+        assert!(unix_socket.exists());
+
+        let listener = bind_unix_socket(&unix_socket).unwrap();
+        assert_eq!(
+            bind_unix_socket(&unix_socket).err().unwrap().to_string(),
+            format!("Another server is already listening on Unix socket {unix_socket:?}"),
+        );
+
+        drop(UnixSocketFile(&unix_socket));
+        drop(listener);
+        assert!(!unix_socket.exists());
+
+        fs::write(&unix_socket, "not a socket").unwrap();
+        assert!(bind_unix_socket(&unix_socket).is_err());
+        assert_eq!(fs::read_to_string(&unix_socket).unwrap(), "not a socket");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
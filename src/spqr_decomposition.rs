@@ -0,0 +1,885 @@
+use std::{collections::HashMap, io::Write, path::Path};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray,
+    index::{DirectedNodeIndex, EdgeIndex, GraphIndexInteger, NodeIndex},
+    io::gfa1::{GfaEdgeData, GfaNodeData},
+};
+use itertools::Itertools;
+use log::info;
+use spqr_shortest_path_index::gfa_graph_extensions::GfaNodeDataExt;
+use spqr_tree::{
+    decomposition::{
+        SPQRDecomposition, SPQRNodeType,
+        builder::SPQRDecompositionBuilder,
+        indices::{ComponentIndex, SPQRNodeIndex},
+    },
+    graph::StaticGraph,
+};
+
+use crate::{
+    io_util::read_optionally_compressed_file,
+    spqr_decomposition::triconnected_components::{
+        ComponentEdge, ComponentType, triconnected_components,
+    },
+};
+
+mod triconnected_components;
+
+/// Marks the absence of a node or edge.
+const NONE: usize = usize::MAX;
+
+/// Reads the SPQR decomposition from the given file in plain text format,
+/// or computes it from the graph if no file is given.
+pub fn load_or_compute_spqr_decomposition<
+    'graph,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    spqr_in: Option<&Path>,
+) -> anyhow::Result<
+    SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+> {
+    if let Some(spqr_in) = spqr_in {
+        // The upstream reader panics on unsupported self-loops, so they are reported beforehand.
+        let (edges, adjacency, self_loops) = undirected_adjacency(graph);
+        check_self_loops(
+            graph,
+            &edges,
+            &adjacency,
+            &self_loops,
+            &block_cut_tree(&adjacency, &edges),
+        )?;
+
+        info!("Reading SPQR decomposition from file {:?}", spqr_in);
+        read_optionally_compressed_file(spqr_in, |reader| {
+            SPQRDecomposition::read_plain_spqr(graph, reader)
+                .with_context(|| format!("Failed to parse SPQR decomposition file {:?}", spqr_in))
+        })
+        .with_context(|| format!("Failed to read SPQR file: {:?}", spqr_in))
+    } else {
+        info!("Computing SPQR decomposition");
+        let spqr_decomposition = compute_spqr_decomposition(graph)?;
+        info!(
+            "SPQR decomposition has {} components, {} blocks and {} SPQR nodes",
+            spqr_decomposition.component_count(),
+            spqr_decomposition.block_count(),
+            spqr_decomposition.spqr_node_count(),
+        );
+        Ok(spqr_decomposition)
+    }
+}
+
+/// Writes the SPQR decomposition in plain text format, such that it can be read with [`SPQRDecomposition::read_plain_spqr`].
+///
+/// Unlike [`SPQRDecomposition::write_plain_spqr`], this writes the cut nodes after the blocks they refer to.
+pub fn write_plain_spqr<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    spqr_decomposition: &SPQRDecomposition<BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    let graph = spqr_decomposition.graph();
+    writeln!(
+        writer,
+        "H v0.1 https://github.com/sebschmi/SPQR-tree-file-format"
+    )?;
+
+    for node in spqr_decomposition.iter_nodes() {
+        let extra_data = spqr_decomposition.node_extra_data(node);
+        if !extra_data.is_empty() {
+            writeln!(writer, "N {} {extra_data}", graph.node_name(node))?;
+        }
+    }
+
+    for (component_index, component) in spqr_decomposition.iter_components() {
+        write!(writer, "G G{component_index}")?;
+        for node in component.iter_nodes() {
+            write!(writer, " {}", graph.node_name(node))?;
+        }
+        writeln!(writer)?;
+
+        for (block_index, block) in spqr_decomposition.iter_blocks_in_component(component_index) {
+            write!(writer, "B B{block_index} G{component_index}")?;
+            for node in block.iter_nodes() {
+                write!(writer, " {}", graph.node_name(node))?;
+            }
+            writeln!(writer)?;
+        }
+
+        for cut_node_index in component.iter_cut_nodes() {
+            let cut_node = spqr_decomposition.cut_node(cut_node_index);
+            write!(writer, "C {}", graph.node_name(cut_node.node()))?;
+            for block_index in cut_node.iter_adjacent_blocks() {
+                write!(writer, " B{block_index}")?;
+            }
+            writeln!(writer)?;
+        }
+
+        for (block_index, _) in spqr_decomposition.iter_blocks_in_component(component_index) {
+            for (spqr_node_index, spqr_node) in
+                spqr_decomposition.iter_spqr_nodes_in_block(block_index)
+            {
+                let spqr_node_name = spqr_decomposition.spqr_node_name(spqr_node_index);
+                let spqr_node_type = match spqr_node.spqr_node_type() {
+                    SPQRNodeType::SNode => "S",
+                    SPQRNodeType::PNode => "P",
+                    SPQRNodeType::RNode => "R",
+                };
+                write!(writer, "{spqr_node_type} {spqr_node_name} B{block_index}")?;
+                for node in spqr_node.iter_nodes() {
+                    write!(writer, " {}", graph.node_name(node))?;
+                }
+                writeln!(writer)?;
+
+                for edge in spqr_node.iter_edges() {
+                    let (u, v) = StaticGraph::edge_endpoints(graph, edge);
+                    write!(
+                        writer,
+                        "E E{edge} {spqr_node_name} B{block_index} {} {}",
+                        graph.node_name(u),
+                        graph.node_name(v),
+                    )?;
+                    let extra_data = spqr_decomposition.edge_extra_data(edge);
+                    if !extra_data.is_empty() {
+                        write!(writer, " {extra_data}")?;
+                    }
+                    writeln!(writer)?;
+                }
+            }
+
+            for (spqr_edge_index, spqr_edge) in
+                spqr_decomposition.iter_spqr_edges_in_block(block_index)
+            {
+                let (a, b) = spqr_edge.endpoints();
+                let (u, v) = spqr_edge.virtual_edge();
+                writeln!(
+                    writer,
+                    "V V{spqr_edge_index} {} {} {} {}",
+                    spqr_decomposition.spqr_node_name(a),
+                    spqr_decomposition.spqr_node_name(b),
+                    graph.node_name(u),
+                    graph.node_name(v),
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the SPQR decomposition of the graph, ignoring the orientations of the edges.
+///
+/// The connected components are split into blocks, the blocks with two nodes become a single P-node,
+/// and the larger blocks are split into their triconnected components.
+///
+/// Self-loops are assigned to an SPQR node containing their node.
+/// This is only possible if the node is in a single block with at least two nodes, otherwise an error naming the segment is returned.
+pub fn compute_spqr_decomposition<
+    'graph,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+) -> anyhow::Result<
+    SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+> {
+    decompose(graph, None).map(|(spqr_decomposition, _)| spqr_decomposition)
+}
+
+/// Computes the SPQR decomposition of the graph like [`compute_spqr_decomposition`],
+/// but copies the decomposition of each connected component that is unchanged in the old decomposition.
+///
+/// A component is unchanged if the old graph has a component with the same node names and lengths, and the same edges.
+/// Returns the decomposition and the node in the graph for each node of an unchanged component in the old graph.
+#[expect(clippy::type_complexity)]
+pub fn update_spqr_decomposition<
+    'graph,
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    old_spqr_decomposition: &SPQRDecomposition<
+        '_,
+        BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    >,
+) -> anyhow::Result<(
+    SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    Vec<Option<NodeIndex<IndexType>>>,
+)> {
+    decompose(graph, Some(old_spqr_decomposition))
+}
+
+#[expect(clippy::type_complexity)]
+fn decompose<'graph, IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    graph: &'graph BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    old_spqr_decomposition: Option<
+        &SPQRDecomposition<'_, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    >,
+) -> anyhow::Result<(
+    SPQRDecomposition<'graph, BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    Vec<Option<NodeIndex<IndexType>>>,
+)> {
+    let node_count = graph.node_count();
+    let (edges, adjacency, self_loops) = undirected_adjacency(graph);
+    let components = block_cut_tree(&adjacency, &edges);
+    check_self_loops(graph, &edges, &adjacency, &self_loops, &components)?;
+
+    let unchanged_components = if let Some(old_spqr_decomposition) = old_spqr_decomposition {
+        let unchanged_components =
+            find_unchanged_components(graph, &components, old_spqr_decomposition);
+        info!(
+            "Copying the SPQR decomposition of {} of {} components",
+            unchanged_components.old_components.iter().flatten().count(),
+            components.len(),
+        );
+        unchanged_components
+    } else {
+        UnchangedComponents {
+            old_components: vec![None; components.len()],
+            old_to_new_nodes: Vec::new(),
+            old_to_new_edges: Vec::new(),
+        }
+    };
+
+    let node_index = NodeIndex::<IndexType>::from_usize;
+    let mut builder = SPQRDecompositionBuilder::new(graph);
+    let mut node_spqr_node = vec![None; node_count];
+    let mut local_ids = vec![NONE; node_count];
+
+    // Copied components already contain their self-loops.
+    let mut is_copied_node = vec![false; node_count];
+    for (component, old_component) in components.iter().zip(&unchanged_components.old_components) {
+        if let Some(old_component) = *old_component {
+            for &node in &component.nodes {
+                is_copied_node[node] = true;
+            }
+            copy_component(
+                &mut builder,
+                old_spqr_decomposition.unwrap(),
+                old_component,
+                &unchanged_components,
+            );
+            continue;
+        }
+
+        let component_index =
+            builder.add_component(component.nodes.iter().copied().map(node_index).collect());
+
+        for block in &component.blocks {
+            let block_index = builder.add_block(
+                component_index,
+                block.nodes.iter().copied().map(node_index).collect(),
+            );
+
+            match block.nodes.len() {
+                // Isolated nodes have no SPQR node.
+                1 => {}
+                2 => {
+                    let spqr_node = builder.add_spqr_node(
+                        block_index,
+                        block.nodes.iter().copied().map(node_index).collect(),
+                        SPQRNodeType::PNode,
+                    );
+                    for &edge in &block.edges {
+                        builder.add_edge_to_spqr_node(EdgeIndex::from_usize(edge), spqr_node);
+                    }
+                    for &node in &block.nodes {
+                        node_spqr_node[node].get_or_insert(spqr_node);
+                    }
+                }
+                _ => {
+                    for (local_id, &node) in block.nodes.iter().enumerate() {
+                        local_ids[node] = local_id;
+                    }
+                    let local_edges: Vec<_> = block
+                        .edges
+                        .iter()
+                        .map(|&edge| (local_ids[edges[edge].0], local_ids[edges[edge].1]))
+                        .collect();
+                    let triconnected_components =
+                        triconnected_components(block.nodes.len(), &local_edges);
+
+                    // The two SPQR nodes containing each virtual edge.
+                    let mut virtual_edge_spqr_nodes: Vec<Vec<SPQRNodeIndex<IndexType>>> =
+                        vec![Vec::new(); triconnected_components.virtual_edges.len()];
+                    let mut is_spqr_node_node = vec![false; block.nodes.len()];
+                    for triconnected_component in &triconnected_components.components {
+                        let endpoints = |component_edge: &ComponentEdge| match *component_edge {
+                            ComponentEdge::Real(edge) => local_edges[edge],
+                            ComponentEdge::Virtual(edge) => {
+                                triconnected_components.virtual_edges[edge]
+                            }
+                        };
+                        let mut spqr_node_nodes = Vec::new();
+                        for (u, v) in triconnected_component.edges.iter().map(endpoints) {
+                            for local_node in [u, v] {
+                                if !is_spqr_node_node[local_node] {
+                                    is_spqr_node_node[local_node] = true;
+                                    spqr_node_nodes.push(local_node);
+                                }
+                            }
+                        }
+                        for &local_node in &spqr_node_nodes {
+                            is_spqr_node_node[local_node] = false;
+                        }
+
+                        let spqr_node = builder.add_spqr_node(
+                            block_index,
+                            spqr_node_nodes
+                                .iter()
+                                .map(|&local_node| node_index(block.nodes[local_node]))
+                                .collect(),
+                            match triconnected_component.component_type {
+                                ComponentType::Bond => SPQRNodeType::PNode,
+                                ComponentType::Polygon => SPQRNodeType::SNode,
+                                ComponentType::Triconnected => SPQRNodeType::RNode,
+                            },
+                        );
+                        for &local_node in &spqr_node_nodes {
+                            node_spqr_node[block.nodes[local_node]].get_or_insert(spqr_node);
+                        }
+
+                        for component_edge in &triconnected_component.edges {
+                            match *component_edge {
+                                ComponentEdge::Real(edge) => builder.add_edge_to_spqr_node(
+                                    EdgeIndex::from_usize(block.edges[edge]),
+                                    spqr_node,
+                                ),
+                                ComponentEdge::Virtual(edge) => {
+                                    virtual_edge_spqr_nodes[edge].push(spqr_node)
+                                }
+                            }
+                        }
+                    }
+
+                    for (virtual_edge, spqr_nodes) in virtual_edge_spqr_nodes.iter().enumerate() {
+                        debug_assert_eq!(spqr_nodes.len(), 2);
+                        let (u, v) = triconnected_components.virtual_edges[virtual_edge];
+                        builder.add_spqr_edge(
+                            Some(block_index).into(),
+                            (spqr_nodes[0], spqr_nodes[1]),
+                            (node_index(block.nodes[u]), node_index(block.nodes[v])),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    for edge in self_loops {
+        if is_copied_node[edges[edge].0] {
+            continue;
+        }
+        let spqr_node = node_spqr_node[edges[edge].0]
+            .expect("a node in a block with at least two nodes has an SPQR node");
+        builder.add_edge_to_spqr_node(EdgeIndex::from_usize(edge), spqr_node);
+    }
+
+    Ok((builder.build(), unchanged_components.old_to_new_nodes))
+}
+
+/// Returns the endpoints of all edges, the adjacency lists without self-loops, and the self-loops.
+#[expect(clippy::type_complexity)]
+fn undirected_adjacency<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+) -> (Vec<(usize, usize)>, Vec<Vec<(usize, usize)>>, Vec<usize>) {
+    let edges: Vec<_> = graph
+        .iter_edges()
+        .map(|edge| {
+            let (u, v) = StaticGraph::edge_endpoints(graph, edge);
+            (u.into_usize(), v.into_usize())
+        })
+        .collect();
+
+    let mut adjacency = vec![Vec::new(); graph.node_count()];
+    let mut self_loops = Vec::new();
+    for (edge, &(u, v)) in edges.iter().enumerate() {
+        if u == v {
+            self_loops.push(edge);
+        } else {
+            adjacency[u].push((v, edge));
+            adjacency[v].push((u, edge));
+        }
+    }
+
+    (edges, adjacency, self_loops)
+}
+
+/// Returns an error naming the first segment with a self-loop that the SPQR decomposition cannot represent.
+///
+/// The upstream data structure assigns self-loops to blocks automatically, which requires their node to be in exactly one block.
+/// Also, self-loops belong to an SPQR node, which requires their node to be in a block with at least two nodes.
+fn check_self_loops<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    edges: &[(usize, usize)],
+    adjacency: &[Vec<(usize, usize)>],
+    self_loops: &[usize],
+    components: &[Component],
+) -> anyhow::Result<()> {
+    let mut node_block_counts = vec![0usize; graph.node_count()];
+    for block in components.iter().flat_map(|component| &component.blocks) {
+        for &node in &block.nodes {
+            node_block_counts[node] += 1;
+        }
+    }
+
+    for &edge in self_loops {
+        let node = edges[edge].0;
+        let name = graph.node_data(NodeIndex::from_usize(node)).name();
+        if adjacency[node].is_empty() {
+            anyhow::bail!(
+                "Segment {name} has a self-loop, but no links to other segments. \
+                 The SPQR decomposition only supports self-loops on segments that are linked to other segments."
+            );
+        }
+        if node_block_counts[node] > 1 {
+            anyhow::bail!(
+                "Segment {name} has a self-loop and is a cut node that lies in {} blocks. \
+                 The SPQR decomposition does not support self-loops on cut nodes.",
+                node_block_counts[node],
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The connected components that are unchanged since the old graph.
+struct UnchangedComponents<IndexType> {
+    /// The old component of each component, if it is unchanged.
+    old_components: Vec<Option<ComponentIndex<IndexType>>>,
+    /// The node of each node of an unchanged old component.
+    old_to_new_nodes: Vec<Option<NodeIndex<IndexType>>>,
+    /// The edge of each edge of an unchanged old component.
+    old_to_new_edges: Vec<Option<EdgeIndex<IndexType>>>,
+}
+
+/// Finds the components that have an old component with the same node names and lengths, and the same edges.
+///
+/// Sequences are not compared, since the SPQR decomposition and the overlay depend only on the lengths of the nodes.
+fn find_unchanged_components<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    components: &[Component],
+    old_spqr_decomposition: &SPQRDecomposition<
+        BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    >,
+) -> UnchangedComponents<IndexType> {
+    let old_graph = old_spqr_decomposition.graph();
+    let old_nodes_by_name: HashMap<_, _> = old_graph
+        .iter_nodes()
+        .map(|old_node| (old_graph.node_data(old_node).name(), old_node))
+        .collect();
+    let mut old_component_node_counts = vec![0; old_spqr_decomposition.component_count()];
+    for old_node in old_graph.iter_nodes() {
+        old_component_node_counts[old_spqr_decomposition
+            .node_component_index(old_node)
+            .into_usize()] += 1;
+    }
+
+    let mut old_components = vec![None; components.len()];
+    let mut new_to_old_nodes = vec![None; graph.node_count()];
+    let mut is_old_component_candidate = vec![false; old_spqr_decomposition.component_count()];
+    for (component, old_component) in components.iter().zip(&mut old_components) {
+        let old_nodes: Option<Vec<_>> = component
+            .nodes
+            .iter()
+            .map(|&node| {
+                let node_data = graph.node_data(NodeIndex::from_usize(node));
+                old_nodes_by_name
+                    .get(&node_data.name())
+                    .copied()
+                    .filter(|&old_node| {
+                        GfaNodeDataExt::<IndexType>::len(old_graph.node_data(old_node))
+                            == GfaNodeDataExt::<IndexType>::len(node_data)
+                    })
+            })
+            .collect();
+        let Some(old_nodes) = old_nodes else {
+            continue;
+        };
+        let candidate = old_spqr_decomposition.node_component_index(old_nodes[0]);
+        if old_component_node_counts[candidate.into_usize()] == old_nodes.len()
+            && old_nodes
+                .iter()
+                .all(|&old_node| old_spqr_decomposition.node_component_index(old_node) == candidate)
+        {
+            *old_component = Some(candidate);
+            is_old_component_candidate[candidate.into_usize()] = true;
+            for (&node, old_node) in component.nodes.iter().zip(old_nodes) {
+                new_to_old_nodes[node] = Some(old_node);
+            }
+        }
+    }
+
+    // Identify the edges by the old nodes they connect, normalised to one of their two directions.
+    let edge_key =
+        |from: DirectedNodeIndex<IndexType>, to: DirectedNodeIndex<IndexType>, overlap: u16| {
+            (from, to, overlap).min((to.invert(), from.invert(), overlap))
+        };
+    let mut component_of_node = vec![NONE; graph.node_count()];
+    for (component_index, component) in components.iter().enumerate() {
+        for &node in &component.nodes {
+            component_of_node[node] = component_index;
+        }
+    }
+    let mut component_edges = vec![Vec::new(); components.len()];
+    for edge in graph.iter_edges() {
+        let edge_view = graph.edge(edge);
+        let component = component_of_node[edge_view.from().into_bidirected().into_usize()];
+        if old_components[component].is_some() {
+            let old_node = |node: DirectedNodeIndex<IndexType>| {
+                node.with_bidirected_node_index(
+                    new_to_old_nodes[node.into_bidirected().into_usize()].unwrap(),
+                )
+            };
+            component_edges[component].push((
+                edge_key(
+                    old_node(edge_view.from()),
+                    old_node(edge_view.to()),
+                    edge_view.data().overlap(),
+                ),
+                edge,
+            ));
+        }
+    }
+    let mut old_component_edges = vec![Vec::new(); old_spqr_decomposition.component_count()];
+    for old_edge in old_graph.iter_edges() {
+        let edge_view = old_graph.edge(old_edge);
+        let old_component = old_spqr_decomposition
+            .node_component_index(edge_view.from().into_bidirected())
+            .into_usize();
+        if is_old_component_candidate[old_component] {
+            old_component_edges[old_component].push((
+                edge_key(edge_view.from(), edge_view.to(), edge_view.data().overlap()),
+                old_edge,
+            ));
+        }
+    }
+
+    let mut old_to_new_nodes = vec![None; old_graph.node_count()];
+    let mut old_to_new_edges = vec![None; old_graph.edge_count()];
+    for ((component, old_component), edges) in components
+        .iter()
+        .zip(&mut old_components)
+        .zip(&mut component_edges)
+    {
+        let Some(old_component_index) = *old_component else {
+            continue;
+        };
+        let old_edges = &mut old_component_edges[old_component_index.into_usize()];
+        edges.sort_unstable();
+        old_edges.sort_unstable();
+        if edges.len() != old_edges.len()
+            || edges
+                .iter()
+                .zip(old_edges.iter())
+                .any(|((key, _), (old_key, _))| key != old_key)
+        {
+            *old_component = None;
+            continue;
+        }
+
+        for &node in &component.nodes {
+            old_to_new_nodes[new_to_old_nodes[node].unwrap().into_usize()] =
+                Some(NodeIndex::from_usize(node));
+        }
+        for (&(_, edge), &(_, old_edge)) in edges.iter().zip(old_edges.iter()) {
+            old_to_new_edges[old_edge.into_usize()] = Some(edge);
+        }
+    }
+
+    UnchangedComponents {
+        old_components,
+        old_to_new_nodes,
+        old_to_new_edges,
+    }
+}
+
+/// Adds the decomposition of the unchanged old component to the builder.
+fn copy_component<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+    builder: &mut SPQRDecompositionBuilder<BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>>,
+    old_spqr_decomposition: &SPQRDecomposition<
+        BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    >,
+    old_component: ComponentIndex<IndexType>,
+    unchanged_components: &UnchangedComponents<IndexType>,
+) {
+    let node = |old_node: NodeIndex<IndexType>| {
+        unchanged_components.old_to_new_nodes[old_node.into_usize()].unwrap()
+    };
+    let edge = |old_edge: EdgeIndex<IndexType>| {
+        unchanged_components.old_to_new_edges[old_edge.into_usize()].unwrap()
+    };
+
+    let component_index = builder.add_component(
+        old_spqr_decomposition
+            .iter_blocks_in_component(old_component)
+            .flat_map(|(_, old_block)| old_block.iter_nodes())
+            .unique()
+            .map(node)
+            .collect(),
+    );
+    for (old_block_index, old_block) in
+        old_spqr_decomposition.iter_blocks_in_component(old_component)
+    {
+        let block_index =
+            builder.add_block(component_index, old_block.iter_nodes().map(node).collect());
+
+        let mut spqr_nodes = HashMap::new();
+        for (old_spqr_node_index, old_spqr_node) in
+            old_spqr_decomposition.iter_spqr_nodes_in_block(old_block_index)
+        {
+            let spqr_node = builder.add_spqr_node(
+                block_index,
+                old_spqr_node.iter_nodes().map(node).collect(),
+                old_spqr_node.spqr_node_type(),
+            );
+            for old_edge in old_spqr_node.iter_edges() {
+                builder.add_edge_to_spqr_node(edge(old_edge), spqr_node);
+            }
+            spqr_nodes.insert(old_spqr_node_index, spqr_node);
+        }
+
+        for (_, old_spqr_edge) in old_spqr_decomposition.iter_spqr_edges_in_block(old_block_index) {
+            let (a, b) = old_spqr_edge.endpoints();
+            let (u, v) = old_spqr_edge.virtual_edge();
+            builder.add_spqr_edge(
+                Some(block_index).into(),
+                (spqr_nodes[&a], spqr_nodes[&b]),
+                (node(u), node(v)),
+            );
+        }
+    }
+}
+
+/// A connected component of the graph.
+struct Component {
+    nodes: Vec<usize>,
+    blocks: Vec<Block>,
+}
+
+/// A biconnected component of the graph.
+struct Block {
+    nodes: Vec<usize>,
+    /// The edges of the block, excluding self-loops.
+    edges: Vec<usize>,
+}
+
+/// Computes the connected components and their blocks with the algorithm by Hopcroft and Tarjan (1973).
+///
+/// The adjacency lists must not contain self-loops.
+/// Nodes without incident edges form a block on their own.
+fn block_cut_tree(adjacency: &[Vec<(usize, usize)>], edges: &[(usize, usize)]) -> Vec<Component> {
+    let node_count = adjacency.len();
+    let mut discovery_time = vec![0; node_count];
+    let mut lowpoint = vec![0; node_count];
+    let mut is_block_node = vec![false; node_count];
+    let mut time = 0;
+    let mut components = Vec::new();
+
+    for root in 0..node_count {
+        if discovery_time[root] != 0 {
+            continue;
+        }
+
+        time += 1;
+        discovery_time[root] = time;
+        lowpoint[root] = time;
+        let mut component = Component {
+            nodes: vec![root],
+            blocks: Vec::new(),
+        };
+        let mut edge_stack = Vec::new();
+        // Contains the node, the tree edge entering it, and the position in its adjacency list.
+        let mut stack = vec![(root, NONE, 0)];
+
+        while let Some((node, parent_edge, position)) = stack.last_mut() {
+            let (node, parent_edge) = (*node, *parent_edge);
+
+            if let Some(&(neighbor, edge)) = adjacency[node].get(*position) {
+                *position += 1;
+                if edge == parent_edge {
+                    continue;
+                }
+
+                if discovery_time[neighbor] == 0 {
+                    time += 1;
+                    discovery_time[neighbor] = time;
+                    lowpoint[neighbor] = time;
+                    component.nodes.push(neighbor);
+                    edge_stack.push(edge);
+                    stack.push((neighbor, edge, 0));
+                } else if discovery_time[neighbor] < discovery_time[node] {
+                    edge_stack.push(edge);
+                    lowpoint[node] = lowpoint[node].min(discovery_time[neighbor]);
+                }
+            } else {
+                stack.pop();
+                let Some(&(parent, _, _)) = stack.last() else {
+                    continue;
+                };
+                lowpoint[parent] = lowpoint[parent].min(lowpoint[node]);
+
+                if lowpoint[node] >= discovery_time[parent] {
+                    // The parent separates the subtree of the node from the rest of the graph.
+                    let mut block = Block {
+                        nodes: Vec::new(),
+                        edges: Vec::new(),
+                    };
+                    loop {
+                        let edge = edge_stack.pop().unwrap();
+                        block.edges.push(edge);
+                        for block_node in [edges[edge].0, edges[edge].1] {
+                            if !is_block_node[block_node] {
+                                is_block_node[block_node] = true;
+                                block.nodes.push(block_node);
+                            }
+                        }
+                        if edge == parent_edge {
+                            break;
+                        }
+                    }
+                    for &block_node in &block.nodes {
+                        is_block_node[block_node] = false;
+                    }
+                    component.blocks.push(block);
+                }
+            }
+        }
+
+        if component.blocks.is_empty() {
+            component.blocks.push(Block {
+                nodes: vec![root],
+                edges: Vec::new(),
+            });
+        }
+        components.push(component);
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs, path::Path};
+
+    use bidirected_adjacency_array::{
+        graph::BidirectedAdjacencyArray,
+        index::GraphIndexInteger,
+        io::gfa1::{GfaEdgeData, GfaNodeData},
+    };
+    use itertools::Itertools;
+    use spqr_tree::{
+        decomposition::{SPQRDecomposition, SPQRNodeType},
+        graph::StaticGraph,
+    };
+
+    use super::{compute_spqr_decomposition, write_plain_spqr};
+    use crate::gfa::{read_gfa, read_gfa_file};
+
+    type SPQRNodeSet = BTreeSet<(SPQRNodeType, Vec<String>, Vec<(String, String)>)>;
+
+    /// Describes the SPQR nodes by their type, their nodes and their edges, independently of their numbering.
+    fn spqr_node_set<IndexType: GraphIndexInteger, NodeData: GfaNodeData, EdgeData: GfaEdgeData>(
+        spqr_decomposition: &SPQRDecomposition<
+            '_,
+            BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+        >,
+    ) -> SPQRNodeSet {
+        let graph = spqr_decomposition.graph();
+        let name = |node| StaticGraph::node_name(graph, node).into_owned();
+        spqr_decomposition
+            .iter_spqr_nodes()
+            .map(|(_, spqr_node)| {
+                let nodes = spqr_node.iter_nodes().map(name).sorted().collect();
+                let edges = spqr_node
+                    .iter_edges()
+                    .map(|edge| {
+                        let (u, v) = StaticGraph::edge_endpoints(graph, edge);
+                        let (u, v) = (name(u), name(v));
+                        (u.clone().min(v.clone()), u.max(v))
+                    })
+                    .sorted()
+                    .collect();
+                (spqr_node.spqr_node_type(), nodes, edges)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tiny1_matches_reference_decomposition() {
+        let graph = read_gfa_file::<u8>(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_files/tiny1_ln.gfa"
+        )))
+        .unwrap();
+        let computed = compute_spqr_decomposition(&graph).unwrap();
+        let reference = SPQRDecomposition::read_plain_spqr(
+            &graph,
+            fs::read(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test_files/tiny1.spqr"
+            ))
+            .unwrap()
+            .as_slice(),
+        )
+        .unwrap();
+
+        assert_eq!(computed.block_count(), reference.block_count());
+        assert_eq!(spqr_node_set(&computed), spqr_node_set(&reference));
+    }
+
+    #[test]
+    fn nested_decomposition_survives_plain_text_round_trip() {
+        // A K4 on a to d, whose link between a and b is parallel to a path through a K4 on e to h,
+        // followed by a cut node d and a triangle on d, i and j.
+        let graph = read_gfa::<u8>(
+            b"S\ta\tA\nS\tb\tA\nS\tc\tA\nS\td\tA\nS\te\tA\nS\tf\tA\nS\tg\tA\nS\th\tA\nS\ti\tA\nS\tj\tA\n\
+              L\ta\t+\tb\t+\t0M\nL\ta\t+\tc\t+\t0M\nL\ta\t+\td\t+\t0M\nL\tb\t+\tc\t+\t0M\n\
+              L\tb\t+\td\t+\t0M\nL\tc\t+\td\t+\t0M\nL\ta\t-\te\t+\t0M\nL\tf\t+\tb\t-\t0M\n\
+              L\te\t+\tf\t+\t0M\nL\te\t+\tg\t+\t0M\nL\te\t+\th\t+\t0M\nL\tf\t+\tg\t+\t0M\n\
+              L\tf\t+\th\t+\t0M\nL\tg\t+\th\t+\t0M\nL\td\t-\ti\t+\t0M\nL\ti\t+\tj\t+\t0M\n\
+              L\tj\t+\td\t-\t0M\n"
+                .as_slice(),
+        )
+        .unwrap();
+        let computed = compute_spqr_decomposition(&graph).unwrap();
+        let types: Vec<_> = spqr_node_set(&computed)
+            .into_iter()
+            .map(|(spqr_node_type, _, _)| spqr_node_type)
+            .collect();
+        assert_eq!(
+            types,
+            [
+                SPQRNodeType::SNode,
+                SPQRNodeType::SNode,
+                SPQRNodeType::PNode,
+                SPQRNodeType::PNode,
+                SPQRNodeType::RNode,
+                SPQRNodeType::RNode,
+            ]
+        );
+
+        let mut plain = Vec::new();
+        write_plain_spqr(&computed, &mut plain).unwrap();
+        let reread = SPQRDecomposition::read_plain_spqr(&graph, plain.as_slice()).unwrap();
+        assert_eq!(computed.block_count(), reread.block_count());
+        assert_eq!(spqr_node_set(&computed), spqr_node_set(&reread));
+    }
+}
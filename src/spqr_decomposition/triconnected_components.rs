@@ -0,0 +1,1166 @@
+//! Computes the triconnected components of a biconnected multigraph.
+//!
+//! This is the linear-time algorithm by Hopcroft and Tarjan (1973),
+//! with the corrections by Gutwenger and Mutzel (2001), "A linear time implementation of SPQR-trees".
+
+/// Marks the absence of a vertex, edge or list item.
+const NONE: usize = usize::MAX;
+
+/// Marks the end of a segment on the triple stack.
+const EOS: Triple = Triple {
+    h: 0,
+    a: NONE,
+    b: NONE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentType {
+    /// Parallel edges between two vertices.
+    Bond,
+    /// A cycle.
+    Polygon,
+    /// A triconnected simple graph.
+    Triconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentEdge {
+    /// An edge of the input graph, given by its index in the input.
+    Real(usize),
+    /// A virtual edge that is shared by exactly two components, given by its index in [`TriconnectedComponents::virtual_edges`].
+    Virtual(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub component_type: ComponentType,
+    pub edges: Vec<ComponentEdge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TriconnectedComponents {
+    pub components: Vec<Component>,
+    /// The endpoints of each virtual edge.
+    pub virtual_edges: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EdgeType {
+    Unvisited,
+    TreeArc,
+    Frond,
+    Removed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Triple {
+    h: usize,
+    a: usize,
+    b: usize,
+}
+
+/// Computes the triconnected components of the given graph.
+///
+/// The graph must be biconnected, have at least three vertices and must not contain self-loops.
+/// Vertices are numbered from zero to `vertex_count - 1`, and edges are given as pairs of their endpoints.
+///
+/// The resulting components are the nodes of the SPQR tree, and the virtual edges are its edges.
+pub fn triconnected_components(
+    vertex_count: usize,
+    edges: &[(usize, usize)],
+) -> TriconnectedComponents {
+    debug_assert!(vertex_count >= 3);
+    debug_assert!(edges.iter().all(|(u, v)| u != v));
+
+    let mut algorithm = Algorithm::new(vertex_count, edges);
+    algorithm.split_multiple_edges();
+    algorithm.dfs1();
+    algorithm.build_acceptable_adjacency_structure();
+    algorithm.path_finder();
+    algorithm.path_search();
+
+    // The remaining edges form the last split component.
+    let remaining_edges = std::mem::take(&mut algorithm.edge_stack);
+    algorithm.new_triconnected_or_polygon_component(remaining_edges);
+
+    algorithm.assemble_triconnected_components()
+}
+
+/// Doubly linked lists over a shared arena, supporting removal of arbitrary items in constant time.
+struct LinkedLists {
+    head: Vec<usize>,
+    tail: Vec<usize>,
+    len: Vec<usize>,
+    value: Vec<usize>,
+    previous: Vec<usize>,
+    next: Vec<usize>,
+}
+
+impl LinkedLists {
+    fn new(list_count: usize) -> Self {
+        Self {
+            head: vec![NONE; list_count],
+            tail: vec![NONE; list_count],
+            len: vec![0; list_count],
+            value: Vec::new(),
+            previous: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+
+    fn push_back(&mut self, list: usize, value: usize) -> usize {
+        let item = self.value.len();
+        self.value.push(value);
+        self.previous.push(self.tail[list]);
+        self.next.push(NONE);
+        if self.tail[list] == NONE {
+            self.head[list] = item;
+        } else {
+            self.next[self.tail[list]] = item;
+        }
+        self.tail[list] = item;
+        self.len[list] += 1;
+        item
+    }
+
+    fn push_front(&mut self, list: usize, value: usize) -> usize {
+        let item = self.value.len();
+        self.value.push(value);
+        self.previous.push(NONE);
+        self.next.push(self.head[list]);
+        if self.head[list] == NONE {
+            self.tail[list] = item;
+        } else {
+            self.previous[self.head[list]] = item;
+        }
+        self.head[list] = item;
+        self.len[list] += 1;
+        item
+    }
+
+    fn remove(&mut self, list: usize, item: usize) {
+        let previous = self.previous[item];
+        let next = self.next[item];
+        if previous == NONE {
+            self.head[list] = next;
+        } else {
+            self.next[previous] = next;
+        }
+        if next == NONE {
+            self.tail[list] = previous;
+        } else {
+            self.previous[next] = previous;
+        }
+        self.len[list] -= 1;
+    }
+}
+
+struct Algorithm {
+    vertex_count: usize,
+    root: usize,
+
+    // Per edge, including virtual edges.
+    source: Vec<usize>,
+    target: Vec<usize>,
+    edge_type: Vec<EdgeType>,
+    /// The index of the edge in the input, or `NONE` for virtual edges.
+    real_edge: Vec<usize>,
+    starts_path: Vec<bool>,
+    /// The item of the edge in the adjacency list of its source.
+    in_adjacency: Vec<usize>,
+    /// The item of the edge in the highpoint list of its target, if it is a frond.
+    in_highpoints: Vec<usize>,
+
+    // Per vertex.
+    initial_adjacency: Vec<Vec<usize>>,
+    dfs_number: Vec<usize>,
+    new_number: Vec<usize>,
+    /// Maps new numbers back to vertices.
+    vertex_at: Vec<usize>,
+    parent: Vec<usize>,
+    tree_arc: Vec<usize>,
+    descendant_count: Vec<usize>,
+    lowpoint1: Vec<usize>,
+    lowpoint2: Vec<usize>,
+    degree: Vec<usize>,
+    /// The outgoing tree arcs and fronds of each vertex.
+    adjacency: LinkedLists,
+    /// The new numbers of the sources of the fronds entering each vertex, in decreasing order.
+    highpoints: LinkedLists,
+
+    edge_stack: Vec<usize>,
+    triple_stack: Vec<Triple>,
+    components: Vec<(ComponentType, Vec<usize>)>,
+}
+
+impl Algorithm {
+    fn new(vertex_count: usize, edges: &[(usize, usize)]) -> Self {
+        let mut algorithm = Self {
+            vertex_count,
+            root: 0,
+            source: Vec::with_capacity(edges.len()),
+            target: Vec::with_capacity(edges.len()),
+            edge_type: Vec::with_capacity(edges.len()),
+            real_edge: Vec::with_capacity(edges.len()),
+            starts_path: Vec::with_capacity(edges.len()),
+            in_adjacency: Vec::with_capacity(edges.len()),
+            in_highpoints: Vec::with_capacity(edges.len()),
+            initial_adjacency: vec![Vec::new(); vertex_count],
+            dfs_number: vec![0; vertex_count],
+            new_number: vec![0; vertex_count],
+            vertex_at: vec![NONE; vertex_count + 1],
+            parent: vec![NONE; vertex_count],
+            tree_arc: vec![NONE; vertex_count],
+            descendant_count: vec![0; vertex_count],
+            lowpoint1: vec![0; vertex_count],
+            lowpoint2: vec![0; vertex_count],
+            degree: vec![0; vertex_count],
+            adjacency: LinkedLists::new(vertex_count),
+            highpoints: LinkedLists::new(vertex_count),
+            edge_stack: Vec::new(),
+            triple_stack: Vec::new(),
+            components: Vec::new(),
+        };
+
+        for (index, &(u, v)) in edges.iter().enumerate() {
+            algorithm.add_edge(u, v, index);
+        }
+
+        algorithm
+    }
+
+    fn add_edge(&mut self, source: usize, target: usize, real_edge: usize) -> usize {
+        let edge = self.source.len();
+        self.source.push(source);
+        self.target.push(target);
+        self.edge_type.push(EdgeType::Unvisited);
+        self.real_edge.push(real_edge);
+        self.starts_path.push(false);
+        self.in_adjacency.push(NONE);
+        self.in_highpoints.push(NONE);
+        edge
+    }
+
+    fn new_virtual_edge(&mut self, source: usize, target: usize) -> usize {
+        self.add_edge(source, target, NONE)
+    }
+
+    fn opposite(&self, edge: usize, vertex: usize) -> usize {
+        if self.source[edge] == vertex {
+            self.target[edge]
+        } else {
+            self.source[edge]
+        }
+    }
+
+    fn new_component(&mut self, component_type: ComponentType, edges: Vec<usize>) {
+        for &edge in &edges {
+            if self.real_edge[edge] != NONE {
+                self.edge_type[edge] = EdgeType::Removed;
+            }
+        }
+        self.components.push((component_type, edges));
+    }
+
+    fn new_triconnected_or_polygon_component(&mut self, edges: Vec<usize>) {
+        // Split components with three edges are triangles, since multiple edges were split off.
+        let component_type = if edges.len() >= 4 {
+            ComponentType::Triconnected
+        } else {
+            ComponentType::Polygon
+        };
+        self.new_component(component_type, edges);
+    }
+
+    /// Replaces each bundle of parallel edges by a single virtual edge, creating a bond for each bundle.
+    fn split_multiple_edges(&mut self) {
+        let mut edges: Vec<_> = (0..self.source.len())
+            .map(|edge| {
+                let (u, v) = (self.source[edge], self.target[edge]);
+                ((u.min(v), u.max(v)), edge)
+            })
+            .collect();
+        edges.sort_unstable();
+
+        let mut remaining_edges = Vec::with_capacity(edges.len());
+        for bundle in edges.chunk_by(|(endpoints1, _), (endpoints2, _)| endpoints1 == endpoints2) {
+            if let [(_, edge)] = bundle {
+                remaining_edges.push(*edge);
+            } else {
+                let ((u, v), _) = bundle[0];
+                let virtual_edge = self.new_virtual_edge(u, v);
+                let mut bond: Vec<_> = bundle.iter().map(|&(_, edge)| edge).collect();
+                bond.push(virtual_edge);
+                self.new_component(ComponentType::Bond, bond);
+                remaining_edges.push(virtual_edge);
+            }
+        }
+
+        for edge in remaining_edges {
+            self.initial_adjacency[self.source[edge]].push(edge);
+            self.initial_adjacency[self.target[edge]].push(edge);
+            self.degree[self.source[edge]] += 1;
+            self.degree[self.target[edge]] += 1;
+        }
+    }
+
+    /// Builds the palm tree by depth-first search and computes the lowpoints and descendant counts.
+    /// Edges are oriented from parent to child for tree arcs, and from descendant to ancestor for fronds.
+    fn dfs1(&mut self) {
+        let root = self.root;
+        let mut counter = 1;
+        self.dfs_number[root] = counter;
+        self.lowpoint1[root] = counter;
+        self.lowpoint2[root] = counter;
+        self.descendant_count[root] = 1;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((v, position)) = stack.last_mut() {
+            let v = *v;
+            if let Some(&edge) = self.initial_adjacency[v].get(*position) {
+                *position += 1;
+                if self.edge_type[edge] != EdgeType::Unvisited {
+                    continue;
+                }
+
+                let w = self.opposite(edge, v);
+                self.source[edge] = v;
+                self.target[edge] = w;
+                if self.dfs_number[w] == 0 {
+                    self.edge_type[edge] = EdgeType::TreeArc;
+                    self.parent[w] = v;
+                    self.tree_arc[w] = edge;
+                    counter += 1;
+                    self.dfs_number[w] = counter;
+                    self.lowpoint1[w] = counter;
+                    self.lowpoint2[w] = counter;
+                    self.descendant_count[w] = 1;
+                    stack.push((w, 0));
+                } else {
+                    self.edge_type[edge] = EdgeType::Frond;
+                    let w_number = self.dfs_number[w];
+                    if w_number < self.lowpoint1[v] {
+                        self.lowpoint2[v] = self.lowpoint1[v];
+                        self.lowpoint1[v] = w_number;
+                    } else if w_number > self.lowpoint1[v] {
+                        self.lowpoint2[v] = self.lowpoint2[v].min(w_number);
+                    }
+                }
+            } else {
+                stack.pop();
+                if let Some(&(u, _)) = stack.last() {
+                    if self.lowpoint1[v] < self.lowpoint1[u] {
+                        self.lowpoint2[u] = self.lowpoint1[u].min(self.lowpoint2[v]);
+                        self.lowpoint1[u] = self.lowpoint1[v];
+                    } else if self.lowpoint1[v] == self.lowpoint1[u] {
+                        self.lowpoint2[u] = self.lowpoint2[u].min(self.lowpoint2[v]);
+                    } else {
+                        self.lowpoint2[u] = self.lowpoint2[u].min(self.lowpoint1[v]);
+                    }
+                    self.descendant_count[u] += self.descendant_count[v];
+                }
+            }
+        }
+
+        debug_assert_eq!(counter, self.vertex_count, "the graph must be connected");
+    }
+
+    /// Orders the adjacency lists such that the path search finds the separation pairs.
+    fn build_acceptable_adjacency_structure(&mut self) {
+        let mut buckets = vec![Vec::new(); 3 * self.vertex_count + 3];
+        for edge in 0..self.source.len() {
+            let (v, w) = (self.source[edge], self.target[edge]);
+            let phi = match self.edge_type[edge] {
+                EdgeType::TreeArc => {
+                    if self.lowpoint2[w] < self.dfs_number[v] {
+                        3 * self.lowpoint1[w]
+                    } else {
+                        3 * self.lowpoint1[w] + 2
+                    }
+                }
+                EdgeType::Frond => 3 * self.dfs_number[w] + 1,
+                EdgeType::Unvisited | EdgeType::Removed => continue,
+            };
+            buckets[phi].push(edge);
+        }
+
+        for edge in buckets.into_iter().flatten() {
+            self.in_adjacency[edge] = self.adjacency.push_back(self.source[edge], edge);
+        }
+    }
+
+    /// Renumbers the vertices such that the paths in the path search are visited in order,
+    /// and computes which edges start a path and the highpoint lists.
+    fn path_finder(&mut self) {
+        let root = self.root;
+        let mut counter = self.vertex_count;
+        let mut new_path = true;
+        self.new_number[root] = counter - self.descendant_count[root] + 1;
+        let mut stack = vec![(root, self.adjacency.head[root])];
+
+        while let Some((v, item)) = stack.last_mut() {
+            let v = *v;
+            if *item == NONE {
+                stack.pop();
+                if !stack.is_empty() {
+                    counter -= 1;
+                }
+                continue;
+            }
+
+            let edge = self.adjacency.value[*item];
+            *item = self.adjacency.next[*item];
+            let w = self.target[edge];
+            if new_path {
+                new_path = false;
+                self.starts_path[edge] = true;
+            }
+
+            if self.edge_type[edge] == EdgeType::TreeArc {
+                self.new_number[w] = counter - self.descendant_count[w] + 1;
+                stack.push((w, self.adjacency.head[w]));
+            } else {
+                self.in_highpoints[edge] = self.highpoints.push_back(w, self.new_number[v]);
+                new_path = true;
+            }
+        }
+
+        let mut old_to_new = vec![0; self.vertex_count + 1];
+        for v in 0..self.vertex_count {
+            old_to_new[self.dfs_number[v]] = self.new_number[v];
+            self.vertex_at[self.new_number[v]] = v;
+        }
+        for v in 0..self.vertex_count {
+            self.lowpoint1[v] = old_to_new[self.lowpoint1[v]];
+            self.lowpoint2[v] = old_to_new[self.lowpoint2[v]];
+        }
+    }
+
+    fn high(&self, vertex: usize) -> usize {
+        let head = self.highpoints.head[vertex];
+        if head == NONE {
+            0
+        } else {
+            self.highpoints.value[head]
+        }
+    }
+
+    fn delete_high(&mut self, edge: usize) {
+        let item = self.in_highpoints[edge];
+        if item != NONE {
+            self.highpoints.remove(self.target[edge], item);
+            self.in_highpoints[edge] = NONE;
+        }
+    }
+
+    fn remove_from_adjacency(&mut self, edge: usize) {
+        self.adjacency
+            .remove(self.source[edge], self.in_adjacency[edge]);
+    }
+
+    fn top_triple(&self) -> Triple {
+        *self.triple_stack.last().unwrap()
+    }
+
+    /// The new number of the target of the first edge in the adjacency list of the vertex.
+    fn first_child_number(&self, vertex: usize) -> usize {
+        let head = self.adjacency.head[vertex];
+        if head == NONE {
+            0
+        } else {
+            self.new_number[self.target[self.adjacency.value[head]]]
+        }
+    }
+
+    /// Finds the separation pairs and splits off the split components.
+    fn path_search(&mut self) {
+        #[derive(Clone, Copy)]
+        struct Frame {
+            v: usize,
+            /// The current item in the adjacency list of `v`.
+            item: usize,
+            /// The item after the current item, saved before the current item may be removed.
+            next_item: usize,
+            /// The original edge of the current item, which may be replaced during the search.
+            edge: usize,
+            /// The number of remaining outgoing edges, not counting already visited tree arcs.
+            out_degree: usize,
+            /// True if the search returned from the tree arc of the current item.
+            returning: bool,
+        }
+
+        impl Frame {
+            fn new(v: usize, algorithm: &Algorithm) -> Self {
+                Self {
+                    v,
+                    item: algorithm.adjacency.head[v],
+                    next_item: NONE,
+                    edge: NONE,
+                    out_degree: algorithm.adjacency.len[v],
+                    returning: false,
+                }
+            }
+        }
+
+        self.triple_stack.push(EOS);
+        let mut stack = vec![Frame::new(self.root, self)];
+
+        while let Some(&frame) = stack.last() {
+            let mut frame = frame;
+
+            if frame.returning {
+                frame.returning = false;
+                frame.out_degree =
+                    self.after_tree_arc(frame.v, frame.item, frame.edge, frame.out_degree);
+                frame.item = frame.next_item;
+                *stack.last_mut().unwrap() = frame;
+                continue;
+            }
+
+            if frame.item == NONE {
+                stack.pop();
+                continue;
+            }
+
+            let v = frame.v;
+            let v_number = self.new_number[v];
+            let item = frame.item;
+            let edge = self.adjacency.value[item];
+            frame.edge = edge;
+            frame.next_item = self.adjacency.next[item];
+            let w = self.target[edge];
+            let w_number = self.new_number[w];
+
+            if self.edge_type[edge] == EdgeType::TreeArc {
+                if self.starts_path[edge] {
+                    let lowpoint1 = self.lowpoint1[w];
+                    if self.top_triple().a != NONE && self.top_triple().a > lowpoint1 {
+                        let mut y = 0;
+                        let mut b = NONE;
+                        while self.top_triple().a != NONE && self.top_triple().a > lowpoint1 {
+                            let triple = self.triple_stack.pop().unwrap();
+                            y = y.max(triple.h);
+                            b = triple.b;
+                        }
+                        self.triple_stack.push(Triple {
+                            h: y.max(w_number + self.descendant_count[w] - 1),
+                            a: lowpoint1,
+                            b,
+                        });
+                    } else {
+                        self.triple_stack.push(Triple {
+                            h: w_number + self.descendant_count[w] - 1,
+                            a: lowpoint1,
+                            b: v_number,
+                        });
+                    }
+                    self.triple_stack.push(EOS);
+                }
+
+                frame.returning = true;
+                *stack.last_mut().unwrap() = frame;
+                stack.push(Frame::new(w, self));
+            } else {
+                // The edge is a frond.
+                if self.starts_path[edge] {
+                    if self.top_triple().a != NONE && self.top_triple().a > w_number {
+                        let mut y = 0;
+                        let mut b = NONE;
+                        while self.top_triple().a != NONE && self.top_triple().a > w_number {
+                            let triple = self.triple_stack.pop().unwrap();
+                            y = y.max(triple.h);
+                            b = triple.b;
+                        }
+                        self.triple_stack.push(Triple {
+                            h: y,
+                            a: w_number,
+                            b,
+                        });
+                    } else {
+                        self.triple_stack.push(Triple {
+                            h: v_number,
+                            a: w_number,
+                            b: v_number,
+                        });
+                    }
+                }
+
+                if w == self.parent[v] {
+                    // The frond is parallel to the tree arc entering `v`.
+                    let tree_arc = self.tree_arc[v];
+                    self.adjacency.remove(v, item);
+                    self.delete_high(edge);
+                    let virtual_edge = self.new_virtual_edge(w, v);
+                    self.new_component(ComponentType::Bond, vec![edge, tree_arc, virtual_edge]);
+                    self.degree[v] -= 1;
+                    self.degree[w] -= 1;
+
+                    let tree_arc_item = self.in_adjacency[tree_arc];
+                    self.adjacency.value[tree_arc_item] = virtual_edge;
+                    self.in_adjacency[virtual_edge] = tree_arc_item;
+                    self.edge_type[virtual_edge] = EdgeType::TreeArc;
+                    self.tree_arc[v] = virtual_edge;
+                } else {
+                    self.edge_stack.push(edge);
+                }
+
+                frame.item = frame.next_item;
+                *stack.last_mut().unwrap() = frame;
+            }
+        }
+    }
+
+    /// Checks for separation pairs after returning from the tree arc in `item` of the adjacency list of `v`.
+    /// Returns the updated number of remaining outgoing edges of `v`.
+    fn after_tree_arc(
+        &mut self,
+        v: usize,
+        item: usize,
+        edge: usize,
+        mut out_degree: usize,
+    ) -> usize {
+        let v_number = self.new_number[v];
+        let mut w = self.target[edge];
+        let mut w_number = self.new_number[w];
+
+        self.edge_stack.push(self.tree_arc[w]);
+
+        // Check for type-2 separation pairs.
+        while v_number != 1
+            && (self.top_triple().a == v_number
+                || (self.degree[w] == 2 && self.first_child_number(w) > w_number))
+        {
+            let Triple { a, b, .. } = self.top_triple();
+            if a == v_number && self.parent[self.vertex_at[b]] == self.vertex_at[a] {
+                self.triple_stack.pop();
+                continue;
+            }
+
+            let mut edge_ab = NONE;
+            let mut virtual_edge;
+            let x;
+            if self.degree[w] == 2 && self.first_child_number(w) > w_number {
+                let e1 = self.edge_stack.pop().unwrap();
+                let e2 = self.edge_stack.pop().unwrap();
+                self.remove_from_adjacency(e2);
+                x = self.target[e2];
+                virtual_edge = self.new_virtual_edge(v, x);
+                self.degree[v] -= 1;
+                self.degree[x] -= 1;
+                self.new_component(ComponentType::Polygon, vec![e1, e2, virtual_edge]);
+
+                if let Some(&top_edge) = self.edge_stack.last()
+                    && self.source[top_edge] == x
+                    && self.target[top_edge] == v
+                {
+                    edge_ab = self.edge_stack.pop().unwrap();
+                    self.remove_from_adjacency(edge_ab);
+                    self.delete_high(edge_ab);
+                }
+            } else {
+                let h = self.top_triple().h;
+                self.triple_stack.pop();
+                let mut component = Vec::new();
+                while let Some(&top_edge) = self.edge_stack.last() {
+                    let (x_number, y_number) = (
+                        self.new_number[self.source[top_edge]],
+                        self.new_number[self.target[top_edge]],
+                    );
+                    if !(a <= x_number && x_number <= h && a <= y_number && y_number <= h) {
+                        break;
+                    }
+
+                    self.edge_stack.pop();
+                    if (x_number == a && y_number == b) || (y_number == a && x_number == b) {
+                        edge_ab = top_edge;
+                        self.remove_from_adjacency(edge_ab);
+                        self.delete_high(edge_ab);
+                    } else {
+                        if self.in_adjacency[top_edge] != item {
+                            self.remove_from_adjacency(top_edge);
+                            self.delete_high(top_edge);
+                        }
+                        component.push(top_edge);
+                        self.degree[self.source[top_edge]] -= 1;
+                        self.degree[self.target[top_edge]] -= 1;
+                    }
+                }
+
+                virtual_edge = self.new_virtual_edge(self.vertex_at[a], self.vertex_at[b]);
+                component.push(virtual_edge);
+                self.new_triconnected_or_polygon_component(component);
+                x = self.vertex_at[b];
+            }
+
+            if edge_ab != NONE {
+                let bond_virtual_edge = self.new_virtual_edge(v, x);
+                self.new_component(
+                    ComponentType::Bond,
+                    vec![edge_ab, virtual_edge, bond_virtual_edge],
+                );
+                virtual_edge = bond_virtual_edge;
+                self.degree[x] -= 1;
+                self.degree[v] -= 1;
+            }
+
+            self.edge_stack.push(virtual_edge);
+            self.adjacency.value[item] = virtual_edge;
+            self.in_adjacency[virtual_edge] = item;
+            self.degree[x] += 1;
+            self.degree[v] += 1;
+            self.parent[x] = v;
+            self.tree_arc[x] = virtual_edge;
+            self.edge_type[virtual_edge] = EdgeType::TreeArc;
+            w = x;
+            w_number = self.new_number[w];
+        }
+
+        // Check for a type-1 separation pair.
+        let lowpoint1 = self.lowpoint1[w];
+        if self.lowpoint2[w] >= v_number
+            && lowpoint1 < v_number
+            && (self.parent[v] != self.root || out_degree >= 2)
+        {
+            let mut component = Vec::new();
+            let subtree = w_number..w_number + self.descendant_count[w];
+            while let Some(&top_edge) = self.edge_stack.last() {
+                let (x_number, y_number) = (
+                    self.new_number[self.source[top_edge]],
+                    self.new_number[self.target[top_edge]],
+                );
+                if !(subtree.contains(&x_number) || subtree.contains(&y_number)) {
+                    break;
+                }
+
+                self.edge_stack.pop();
+                component.push(top_edge);
+                self.delete_high(top_edge);
+                self.degree[self.source[top_edge]] -= 1;
+                self.degree[self.target[top_edge]] -= 1;
+            }
+
+            let lowpoint1_vertex = self.vertex_at[lowpoint1];
+            let mut virtual_edge = self.new_virtual_edge(v, lowpoint1_vertex);
+            component.push(virtual_edge);
+            self.new_triconnected_or_polygon_component(component);
+
+            if let Some(&top_edge) = self.edge_stack.last() {
+                let (x_number, y_number) = (
+                    self.new_number[self.source[top_edge]],
+                    self.new_number[self.target[top_edge]],
+                );
+                if (x_number == v_number && y_number == lowpoint1)
+                    || (y_number == v_number && x_number == lowpoint1)
+                {
+                    self.edge_stack.pop();
+                    if self.in_adjacency[top_edge] != item {
+                        self.remove_from_adjacency(top_edge);
+                    }
+                    let bond_virtual_edge = self.new_virtual_edge(v, lowpoint1_vertex);
+                    self.in_highpoints[bond_virtual_edge] = self.in_highpoints[top_edge];
+                    self.in_highpoints[top_edge] = NONE;
+                    self.new_component(
+                        ComponentType::Bond,
+                        vec![top_edge, virtual_edge, bond_virtual_edge],
+                    );
+                    virtual_edge = bond_virtual_edge;
+                    self.degree[v] -= 1;
+                    self.degree[lowpoint1_vertex] -= 1;
+                }
+            }
+
+            if lowpoint1_vertex != self.parent[v] {
+                self.edge_stack.push(virtual_edge);
+                self.adjacency.value[item] = virtual_edge;
+                self.in_adjacency[virtual_edge] = item;
+                self.edge_type[virtual_edge] = EdgeType::Frond;
+                if self.in_highpoints[virtual_edge] == NONE
+                    && self.high(lowpoint1_vertex) < v_number
+                {
+                    self.in_highpoints[virtual_edge] =
+                        self.highpoints.push_front(lowpoint1_vertex, v_number);
+                }
+                self.degree[v] += 1;
+                self.degree[lowpoint1_vertex] += 1;
+            } else {
+                // The virtual edge is parallel to the tree arc entering `v`.
+                self.adjacency.remove(v, item);
+                let tree_arc = self.tree_arc[v];
+                let bond_virtual_edge = self.new_virtual_edge(lowpoint1_vertex, v);
+                self.new_component(
+                    ComponentType::Bond,
+                    vec![virtual_edge, bond_virtual_edge, tree_arc],
+                );
+
+                let tree_arc_item = self.in_adjacency[tree_arc];
+                self.adjacency.value[tree_arc_item] = bond_virtual_edge;
+                self.in_adjacency[bond_virtual_edge] = tree_arc_item;
+                self.edge_type[bond_virtual_edge] = EdgeType::TreeArc;
+                self.tree_arc[v] = bond_virtual_edge;
+            }
+        }
+
+        if self.starts_path[edge] {
+            while self.top_triple() != EOS {
+                self.triple_stack.pop();
+            }
+            self.triple_stack.pop();
+        }
+
+        while let Some(&triple) = self.triple_stack.last()
+            && triple != EOS
+            && triple.a != v_number
+            && triple.b != v_number
+            && self.high(v) > triple.h
+        {
+            self.triple_stack.pop();
+        }
+
+        out_degree -= 1;
+        out_degree
+    }
+
+    /// Merges bonds with adjacent bonds and polygons with adjacent polygons.
+    fn assemble_triconnected_components(mut self) -> TriconnectedComponents {
+        // The (up to) two components containing each edge.
+        let mut edge_components = vec![[NONE; 2]; self.source.len()];
+        for (component_index, (_, edges)) in self.components.iter().enumerate() {
+            for &edge in edges {
+                let slots = &mut edge_components[edge];
+                if slots[0] == NONE {
+                    slots[0] = component_index;
+                } else {
+                    debug_assert_eq!(slots[1], NONE);
+                    slots[1] = component_index;
+                }
+            }
+        }
+
+        for component_index in 0..self.components.len() {
+            let component_type = self.components[component_index].0;
+            if component_type == ComponentType::Triconnected
+                || self.components[component_index].1.is_empty()
+            {
+                continue;
+            }
+
+            let mut edges = std::mem::take(&mut self.components[component_index].1);
+            let mut position = 0;
+            while position < edges.len() {
+                let edge = edges[position];
+                position += 1;
+                if self.real_edge[edge] != NONE {
+                    continue;
+                }
+
+                let [first, second] = edge_components[edge];
+                let other = if first == component_index {
+                    second
+                } else {
+                    first
+                };
+                debug_assert_ne!(other, component_index);
+                if self.components[other].0 != component_type {
+                    continue;
+                }
+
+                // Merge the other component into this one, dissolving the shared virtual edge.
+                edges[position - 1] = NONE;
+                let other_edges = std::mem::take(&mut self.components[other].1);
+                for other_edge in other_edges {
+                    if other_edge == edge {
+                        continue;
+                    }
+                    for slot in &mut edge_components[other_edge] {
+                        if *slot == other {
+                            *slot = component_index;
+                        }
+                    }
+                    edges.push(other_edge);
+                }
+            }
+
+            edges.retain(|&edge| edge != NONE);
+            self.components[component_index].1 = edges;
+        }
+
+        // Number the remaining virtual edges.
+        let mut virtual_edge_ids = vec![NONE; self.source.len()];
+        let mut virtual_edges = Vec::new();
+        let components = self
+            .components
+            .into_iter()
+            .filter(|(_, edges)| !edges.is_empty())
+            .map(|(component_type, edges)| Component {
+                component_type,
+                edges: edges
+                    .into_iter()
+                    .map(|edge| {
+                        if self.real_edge[edge] != NONE {
+                            ComponentEdge::Real(self.real_edge[edge])
+                        } else {
+                            if virtual_edge_ids[edge] == NONE {
+                                virtual_edge_ids[edge] = virtual_edges.len();
+                                virtual_edges.push((self.source[edge], self.target[edge]));
+                            }
+                            ComponentEdge::Virtual(virtual_edge_ids[edge])
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        TriconnectedComponents {
+            components,
+            virtual_edges,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::{ComponentEdge, ComponentType, TriconnectedComponents, triconnected_components};
+
+    /// Computes the triconnected components and checks that they form the unique SPQR decomposition of the graph.
+    ///
+    /// Returns the number of bonds, polygons and triconnected components.
+    fn decompose_and_check(vertex_count: usize, edges: &[(usize, usize)]) -> [usize; 3] {
+        let TriconnectedComponents {
+            components,
+            virtual_edges,
+        } = triconnected_components(vertex_count, edges);
+
+        // Every real edge lies in exactly one component, and every virtual edge in exactly two.
+        let mut real_edge_count = vec![0; edges.len()];
+        let mut virtual_edge_components = vec![Vec::new(); virtual_edges.len()];
+        for (component_index, component) in components.iter().enumerate() {
+            for &edge in &component.edges {
+                match edge {
+                    ComponentEdge::Real(edge) => real_edge_count[edge] += 1,
+                    ComponentEdge::Virtual(edge) => {
+                        virtual_edge_components[edge].push(component_index)
+                    }
+                }
+            }
+        }
+        assert!(real_edge_count.iter().all(|&count| count == 1));
+        assert!(
+            virtual_edge_components
+                .iter()
+                .all(|components| components.len() == 2)
+        );
+
+        // The components are connected by the virtual edges into a tree.
+        assert_eq!(components.len(), virtual_edges.len() + 1);
+        let tree_edges: Vec<_> = virtual_edge_components
+            .iter()
+            .map(|components| (components[0], components[1]))
+            .collect();
+        assert!(is_connected(components.len(), &tree_edges, &[]));
+
+        // No two bonds and no two polygons are adjacent.
+        for components_of_edge in &virtual_edge_components {
+            let [a, b] = [components_of_edge[0], components_of_edge[1]]
+                .map(|component_index| components[component_index].component_type);
+            assert!(a != b || a == ComponentType::Triconnected);
+        }
+
+        let mut counts = [0; 3];
+        for component in &components {
+            let component_edges: Vec<_> = component
+                .edges
+                .iter()
+                .map(|&edge| {
+                    let (u, v) = match edge {
+                        ComponentEdge::Real(edge) => edges[edge],
+                        ComponentEdge::Virtual(edge) => virtual_edges[edge],
+                    };
+                    (u.min(v), u.max(v))
+                })
+                .collect();
+            assert!(component_edges.len() >= 3);
+
+            // Renumber the vertices of the component.
+            let mut vertex_ids = HashMap::new();
+            for &(u, v) in &component_edges {
+                for vertex in [u, v] {
+                    let next_id = vertex_ids.len();
+                    vertex_ids.entry(vertex).or_insert(next_id);
+                }
+            }
+            let local_edges: Vec<_> = component_edges
+                .iter()
+                .map(|(u, v)| (vertex_ids[u], vertex_ids[v]))
+                .collect();
+            let local_vertex_count = vertex_ids.len();
+
+            match component.component_type {
+                ComponentType::Bond => {
+                    assert_eq!(local_vertex_count, 2);
+                    counts[0] += 1;
+                }
+                ComponentType::Polygon => {
+                    // A connected graph where every vertex has degree two is a cycle.
+                    assert_eq!(local_vertex_count, local_edges.len());
+                    let mut degrees = vec![0; local_vertex_count];
+                    for &(u, v) in &local_edges {
+                        degrees[u] += 1;
+                        degrees[v] += 1;
+                    }
+                    assert!(degrees.iter().all(|&degree| degree == 2));
+                    assert!(is_connected(local_vertex_count, &local_edges, &[]));
+                    counts[1] += 1;
+                }
+                ComponentType::Triconnected => {
+                    assert!(local_vertex_count >= 4);
+                    let distinct_edges: HashSet<_> = component_edges.iter().collect();
+                    assert_eq!(distinct_edges.len(), component_edges.len());
+                    for u in 0..local_vertex_count {
+                        for v in u + 1..local_vertex_count {
+                            assert!(is_connected(local_vertex_count, &local_edges, &[u, v]));
+                        }
+                    }
+                    counts[2] += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// Returns true if the graph is connected after removing the given vertices.
+    fn is_connected(vertex_count: usize, edges: &[(usize, usize)], removed: &[usize]) -> bool {
+        let mut adjacency = vec![Vec::new(); vertex_count];
+        for &(u, v) in edges {
+            if !removed.contains(&u) && !removed.contains(&v) {
+                adjacency[u].push(v);
+                adjacency[v].push(u);
+            }
+        }
+
+        let Some(start) = (0..vertex_count).find(|vertex| !removed.contains(vertex)) else {
+            return true;
+        };
+        let mut visited = vec![false; vertex_count];
+        visited[start] = true;
+        let mut stack = vec![start];
+        while let Some(vertex) = stack.pop() {
+            for &neighbour in &adjacency[vertex] {
+                if !visited[neighbour] {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        (0..vertex_count).all(|vertex| visited[vertex] || removed.contains(&vertex))
+    }
+
+    #[test]
+    fn cycle() {
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)];
+        assert_eq!(decompose_and_check(5, &edges), [0, 1, 0]);
+    }
+
+    #[test]
+    fn multi_edges() {
+        // A triangle where one edge exists three times.
+        let edges = [(0, 1), (1, 2), (2, 0), (1, 0), (0, 1)];
+        assert_eq!(decompose_and_check(3, &edges), [1, 1, 0]);
+    }
+
+    #[test]
+    fn p_node() {
+        // Three paths of length two between vertices 0 and 1.
+        let edges = [(0, 2), (2, 1), (0, 3), (3, 1), (1, 4), (4, 0)];
+        assert_eq!(decompose_and_check(5, &edges), [1, 3, 0]);
+    }
+
+    #[test]
+    fn r_node() {
+        let k4 = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+        assert_eq!(decompose_and_check(4, &k4), [0, 0, 1]);
+
+        // A wheel with five spokes.
+        let wheel = [
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 4),
+            (4, 0),
+            (5, 0),
+            (5, 1),
+            (5, 2),
+            (5, 3),
+            (5, 4),
+        ];
+        assert_eq!(decompose_and_check(6, &wheel), [0, 0, 1]);
+    }
+
+    #[test]
+    fn nested_components() {
+        // A K4 on 0 to 3, whose edge {0, 1} is parallel to the path 0, 4, 5, 1,
+        // whose edge {4, 5} is in turn part of a K4 on 4 to 7.
+        // Both real edges {0, 1} and {4, 5} end up in bonds.
+        let edges = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (1, 2),
+            (1, 3),
+            (2, 3),
+            (0, 4),
+            (5, 1),
+            (4, 5),
+            (4, 6),
+            (4, 7),
+            (5, 6),
+            (5, 7),
+            (6, 7),
+        ];
+        assert_eq!(decompose_and_check(8, &edges), [2, 1, 2]);
+
+        // Three paths between 0 and 1, one of which passes through a K4 on 4 to 7 without the edge {4, 5}.
+        // The polygons are joined by a bond that contains no real edge.
+        let edges = [
+            (0, 2),
+            (2, 3),
+            (3, 1),
+            (0, 8),
+            (8, 1),
+            (0, 4),
+            (5, 1),
+            (4, 6),
+            (4, 7),
+            (5, 6),
+            (5, 7),
+            (6, 7),
+        ];
+        assert_eq!(decompose_and_check(9, &edges), [1, 3, 1]);
+    }
+
+    #[test]
+    fn random_graphs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            // A Hamiltonian cycle makes the graph biconnected.
+            let vertex_count = rng.random_range(3..40);
+            let mut edges: Vec<_> = (0..vertex_count)
+                .map(|vertex| (vertex, (vertex + 1) % vertex_count))
+                .collect();
+            for _ in 0..rng.random_range(0..vertex_count) {
+                let u = rng.random_range(0..vertex_count);
+                let v = rng.random_range(0..vertex_count);
+                if u != v {
+                    edges.push((u, v));
+                }
+            }
+
+            decompose_and_check(vertex_count, &edges);
+        }
+    }
+}
@@ -1,18 +1,18 @@
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{io::Write, path::PathBuf};
 
 use anyhow::Context;
-use bidirected_adjacency_array::{
-    graph::BidirectedAdjacencyArray,
-    index::GraphIndexInteger,
-    io::gfa1::{PlainGfaEdgeData, PlainGfaNodeData},
-};
+use bidirected_adjacency_array::index::GraphIndexInteger;
 use clap::Parser;
 use itertools::Itertools;
 use log::{LevelFilter, info};
 use serde::{Deserialize, Serialize};
-use spqr_tree::decomposition::SPQRDecomposition;
 
-use crate::io_util::read_optionally_compressed_file;
+use crate::{
+    gfa::read_gfa_file,
+    io_util::{ensure_stdio_used_at_most_once, summary_writer, write_optionally_compressed_file},
+    spqr_decomposition::load_or_compute_spqr_decomposition,
+    word_size::select_word_size,
+};
 
 #[derive(Parser)]
 pub struct Cli {
@@ -24,8 +24,9 @@ pub struct Cli {
     graph_gfa_in: PathBuf,
 
     /// The SPQR decomposition in plain text format.
+    /// If it is not given, then it is computed from the graph.
     #[clap(long)]
-    spqr_in: PathBuf,
+    spqr_in: Option<PathBuf>,
 
     /// The output file for the statistics in JSON format.
     #[clap(long)]
@@ -37,9 +38,16 @@ pub struct Cli {
 
     /// The integer size to use in all data structures.
     /// Supported values are 8, 16, 32, and 64.
-    /// If the program crashes during reading the graph, try using a larger word size.
-    #[clap(long, default_value = "32")]
-    word_size: u8,
+    /// If it is not given, then the smallest word size that fits the graph is selected.
+    #[clap(long)]
+    word_size: Option<u8>,
+
+    /// The compression level for compressed output files, whose format is selected by their extension
+    /// (`.gz`, `.bgz`, `.zst` or `.xz`).
+    /// Supported are 0 to 9 for gzip, BGZF and xz, and 0 to 22 for zstd.
+    /// If it is not given, then the default level of each format is used.
+    #[clap(long)]
+    compression_level: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,32 +68,32 @@ struct Statistics<IndexType> {
 }
 
 pub fn run(cli: Cli) -> anyhow::Result<()> {
-    match cli.word_size {
+    ensure_stdio_used_at_most_once(
+        [Some(cli.graph_gfa_in.as_path()), cli.spqr_in.as_deref()]
+            .into_iter()
+            .flatten(),
+        [
+            cli.statistics_json_out.as_deref(),
+            cli.statistics_toml_out.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+    )?;
+
+    match select_word_size(&cli.graph_gfa_in, cli.word_size)? {
         8 => run_with_word_size::<u8>(cli),
         16 => run_with_word_size::<u16>(cli),
         32 => run_with_word_size::<u32>(cli),
         64 => run_with_word_size::<u64>(cli),
-        _ => anyhow::bail!(
-            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
-            cli.word_size
-        ),
+        word_size => unreachable!("Unsupported word size: {word_size}"),
     }
 }
 
 fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<()> {
     info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
-    let graph = read_optionally_compressed_file(&cli.graph_gfa_in, |reader| {
-        BidirectedAdjacencyArray::<IndexType, PlainGfaNodeData, PlainGfaEdgeData>::read_gfa1(reader)
-            .with_context(|| format!("Failed to parse GFA file {:?}", cli.graph_gfa_in))
-    })
-    .with_context(|| format!("Failed to read GFA file: {:?}", cli.graph_gfa_in))?;
-
-    info!("Reading SPQR decomposition from file {:?}", cli.spqr_in);
-    let spqr_decomposition = read_optionally_compressed_file(&cli.spqr_in, |reader| {
-        SPQRDecomposition::read_plain_spqr(&graph, reader)
-            .with_context(|| format!("Failed to parse SPQR decomposition file {:?}", cli.spqr_in))
-    })
-    .with_context(|| format!("Failed to read SPQR file: {:?}", cli.spqr_in))?;
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+
+    let spqr_decomposition = load_or_compute_spqr_decomposition(&graph, cli.spqr_in.as_deref())?;
 
     info!("Collecting statistics");
     let statistics = Statistics {
@@ -130,28 +138,38 @@ fn run_with_word_size<IndexType: GraphIndexInteger>(cli: Cli) -> anyhow::Result<
     };
 
     info!("Printing short statistics");
-    println!("node_count = {}", statistics.node_count);
-    println!("edge_count = {}", statistics.edge_count);
-    println!("component_count = {}", statistics.component_count);
-    println!("block_count = {}", statistics.block_count);
-    println!("spqr_node_count = {}", statistics.spqr_node_count);
+    let mut summary = summary_writer(
+        [
+            cli.statistics_json_out.as_deref(),
+            cli.statistics_toml_out.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    writeln!(summary, "node_count = {}", statistics.node_count)?;
+    writeln!(summary, "edge_count = {}", statistics.edge_count)?;
+    writeln!(summary, "component_count = {}", statistics.component_count)?;
+    writeln!(summary, "block_count = {}", statistics.block_count)?;
+    writeln!(summary, "spqr_node_count = {}", statistics.spqr_node_count)?;
 
     if let Some(json_out) = cli.statistics_json_out {
         info!("Writing full statistics to JSON file {:?}", json_out);
-        let mut file = File::create(&json_out)
-            .with_context(|| format!("Failed to create JSON output file {:?}", json_out))?;
-        serde_json::to_writer(&mut file, &statistics)
-            .with_context(|| format!("Failed to write statistics to JSON file {:?}", json_out))?;
+        write_optionally_compressed_file(&json_out, cli.compression_level, |writer| {
+            serde_json::to_writer(writer, &statistics)?;
+            Ok(())
+        })
+        .with_context(|| format!("Failed to write statistics to JSON file {:?}", json_out))?;
     }
 
     if let Some(toml_out) = cli.statistics_toml_out {
         info!("Writing full statistics to TOML file {:?}", toml_out);
-        let mut file = File::create(&toml_out)
-            .with_context(|| format!("Failed to create TOML output file {:?}", toml_out))?;
         let toml_string =
             toml::to_string(&statistics).with_context(|| "Failed to format statistics as TOML")?;
-        file.write_all(toml_string.as_bytes())
-            .with_context(|| format!("Failed to write TOML output file {:?}", toml_out))?;
+        write_optionally_compressed_file(&toml_out, cli.compression_level, |writer| {
+            writer.write_all(toml_string.as_bytes())?;
+            Ok(())
+        })
+        .with_context(|| format!("Failed to write TOML output file {:?}", toml_out))?;
     }
 
     info!("Finished");
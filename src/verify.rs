@@ -0,0 +1,435 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use bidirected_adjacency_array::{
+    graph::BidirectedAdjacencyArray,
+    index::GraphIndexInteger,
+    io::gfa1::{GfaEdgeData, GfaNodeData},
+};
+use clap::Parser;
+use indicatif::ProgressBar;
+use itertools::Itertools;
+use log::{LevelFilter, info, warn};
+use rand::{SeedableRng, rngs::StdRng};
+use spqr_shortest_path_index::{location::GfaLocation, path::GfaPathLength};
+use spqr_tree::decomposition::SPQRDecomposition;
+
+use crate::{
+    dijkstra::{GfaDijkstra, OverlayDijkstra, edge_length},
+    generate_queries::{LocationDistribution, LocationSampler, OrientationDistribution},
+    gfa::read_gfa_file,
+    index::{IndexHeader, read_index_header},
+    io_util::{ensure_stdio_used_at_most_once, open_optionally_compressed_file},
+    matrix::format_location,
+    overlay::Overlay,
+    query::{Query, QueryParser, execute_queries},
+};
+
+#[derive(Parser)]
+pub struct Cli {
+    #[clap(long, default_value = "info")]
+    pub(crate) log_level: LevelFilter,
+
+    /// The GFA file containing the graph that the index was built from.
+    #[clap(long)]
+    graph_gfa_in: PathBuf,
+
+    /// The index file to verify.
+    #[clap(long)]
+    index_in: PathBuf,
+
+    /// A tab-separated file containing the queries to verify, in the same format as for `biopath query`.
+    #[clap(long, required_unless_present = "random_queries")]
+    query_in: Option<PathBuf>,
+
+    /// Verify this many random queries instead of reading them from a file.
+    /// Each random query has one source and one target location, chosen uniformly among the nodes, orientations and offsets.
+    #[clap(long, conflicts_with = "query_in")]
+    random_queries: Option<usize>,
+
+    /// The seed for generating random queries.
+    #[clap(long, default_value = "0", requires = "random_queries")]
+    seed: u64,
+
+    /// The number of threads to execute queries with.
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    threads: u16,
+}
+
+/// The number of queries that are read and verified at once.
+const VERIFY_BATCH_SIZE: usize = 1 << 12;
+
+/// The number of queries that a thread takes at once.
+const VERIFY_CHUNK_SIZE: usize = 16;
+
+pub fn run(cli: Cli) -> anyhow::Result<()> {
+    ensure_stdio_used_at_most_once(
+        [
+            Some(cli.graph_gfa_in.as_path()),
+            Some(cli.index_in.as_path()),
+            cli.query_in.as_deref(),
+        ]
+        .into_iter()
+        .flatten(),
+        [],
+    )?;
+
+    let (index_header, index_file_reader) = read_index_header(&cli.index_in)?;
+
+    match index_header.word_size {
+        8 => run_with_word_size::<u8>(cli, index_header, index_file_reader),
+        16 => run_with_word_size::<u16>(cli, index_header, index_file_reader),
+        32 => run_with_word_size::<u32>(cli, index_header, index_file_reader),
+        64 => run_with_word_size::<u64>(cli, index_header, index_file_reader),
+        _ => anyhow::bail!(
+            "Unsupported word size: {}. Supported are 8, 16, 32 and 64.",
+            index_header.word_size
+        ),
+    }
+}
+
+fn run_with_word_size<IndexType: GraphIndexInteger + FromStr + Send + Sync>(
+    cli: Cli,
+    index_header: IndexHeader,
+    mut index_file_reader: impl BufRead,
+) -> anyhow::Result<()>
+where
+    <IndexType as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+{
+    info!("Reading graph from GFA file {:?}", cli.graph_gfa_in);
+    let graph = read_gfa_file::<IndexType>(&cli.graph_gfa_in)?;
+    info!(
+        "Graph has {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count(),
+    );
+
+    index_header.check_graph(&graph, &cli.index_in)?;
+
+    info!("Reading index from file {:?}", cli.index_in);
+    let spqr_decomposition = SPQRDecomposition::read_binary(&graph, &mut index_file_reader)
+        .with_context(|| {
+            format!(
+                "Failed to read SPQR decomposition from index file: {:?}",
+                cli.index_in
+            )
+        })?;
+    index_header.check_spqr_decomposition(&spqr_decomposition, &cli.index_in)?;
+    let overlay = Overlay::read_binary(&graph, &spqr_decomposition, index_file_reader)
+        .with_context(|| format!("Failed to read index file: {:?}", cli.index_in))?;
+
+    let queries: Box<dyn Iterator<Item = anyhow::Result<Query<IndexType>>>> =
+        if let Some(query_in) = &cli.query_in {
+            info!("Reading queries from file {:?}", query_in);
+            let query_parser = QueryParser::new(&graph);
+            let reader = BufReader::new(
+                open_optionally_compressed_file(query_in)
+                    .with_context(|| format!("Failed to open query file {:?}", query_in))?,
+            );
+            Box::new(reader.lines().enumerate().map(move |(line_index, line)| {
+                let line =
+                    line.with_context(|| format!("Failed to read query file {:?}", query_in))?;
+                query_parser.parse_query(&line).with_context(|| {
+                    format!("Invalid query in line {} of {:?}", line_index + 1, query_in)
+                })
+            }))
+        } else {
+            let random_queries = cli.random_queries.unwrap();
+            if graph.node_count() == 0 {
+                anyhow::bail!("Cannot generate random queries for an empty graph");
+            }
+            info!(
+                "Generating {} random queries with seed {}",
+                random_queries, cli.seed
+            );
+            let mut rng = StdRng::seed_from_u64(cli.seed);
+            let sampler = LocationSampler::new(&graph, graph.iter_nodes());
+            let mut random_location = move || {
+                sampler.sample(
+                    &mut rng,
+                    LocationDistribution::Nodes,
+                    OrientationDistribution::Random,
+                )
+            };
+            Box::new(
+                (0..random_queries)
+                    .map(move |_| Ok(Query::new(random_location(), vec![random_location()]))),
+            )
+        };
+
+    info!("Initialising {} search worker(s)", cli.threads);
+    let mut overlay_workers = (0..cli.threads)
+        .map(|_| OverlayDijkstra::new(&overlay))
+        .collect_vec();
+    let mut dijkstra_workers = (0..cli.threads)
+        .map(|_| GfaDijkstra::new(&graph))
+        .collect_vec();
+
+    let mut query_count = 0;
+    let mut target_count = 0;
+    let mut mismatch_count = 0;
+    let mut overlay_time = Duration::ZERO;
+    let mut dijkstra_time = Duration::ZERO;
+    let mut reference_time = Duration::ZERO;
+    let progress_bar = ProgressBar::no_length().with_message("Verifying queries");
+    for batch in &queries.chunks(VERIFY_BATCH_SIZE) {
+        let mut overlay_queries: Vec<_> = batch.try_collect()?;
+        let mut dijkstra_queries = overlay_queries
+            .iter()
+            .map(|query| Query::new(query.source(), query.targets().to_vec()))
+            .collect_vec();
+
+        let start = Instant::now();
+        execute_queries(
+            &mut overlay_workers,
+            &mut overlay_queries,
+            VERIFY_CHUNK_SIZE,
+            &|search, query| {
+                query.execute(search, false);
+            },
+        );
+        overlay_time += start.elapsed();
+
+        let start = Instant::now();
+        execute_queries(
+            &mut dijkstra_workers,
+            &mut dijkstra_queries,
+            VERIFY_CHUNK_SIZE,
+            &|search, query| {
+                query.execute(search, false);
+            },
+        );
+        dijkstra_time += start.elapsed();
+
+        let start = Instant::now();
+        let reference_distances = thread::scope(|scope| {
+            let chunk_size = overlay_queries
+                .len()
+                .div_ceil(usize::from(cli.threads))
+                .max(1);
+            let handles = overlay_queries
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let graph = &graph;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|query| {
+                                reference_distances(graph, query.source(), query.targets())
+                            })
+                            .collect_vec()
+                    })
+                })
+                .collect_vec();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect_vec()
+        });
+        reference_time += start.elapsed();
+
+        for (query_index, ((overlay_query, dijkstra_query), reference_distances)) in overlay_queries
+            .iter()
+            .zip(&dijkstra_queries)
+            .zip(&reference_distances)
+            .enumerate()
+        {
+            for (((target, overlay_distance), dijkstra_distance), reference_distance) in
+                overlay_query
+                    .targets()
+                    .iter()
+                    .zip(overlay_query.distances())
+                    .zip(dijkstra_query.distances())
+                    .zip(reference_distances)
+            {
+                let overlay_distance = overlay_distance.into_option();
+                let dijkstra_distance = dijkstra_distance.into_option();
+                if overlay_distance != *reference_distance
+                    || dijkstra_distance != *reference_distance
+                {
+                    mismatch_count += 1;
+                    warn!(
+                        "Mismatch in query {} from {} to {}: the index gives distance {} and Dijkstra gives distance {}, but the reference search gives distance {}",
+                        query_count + query_index + 1,
+                        format_location(&graph, overlay_query.source()),
+                        format_location(&graph, *target),
+                        format_distance(overlay_distance),
+                        format_distance(dijkstra_distance),
+                        format_distance(*reference_distance),
+                    );
+                }
+            }
+            target_count += overlay_query.targets().len();
+        }
+
+        query_count += overlay_queries.len();
+        progress_bar.inc(overlay_queries.len().try_into().unwrap());
+    }
+    progress_bar.finish_and_clear();
+
+    info!(
+        "Verified {} queries with {} targets in {:.2?} with the index, {:.2?} with Dijkstra and {:.2?} with the reference search",
+        query_count, target_count, overlay_time, dijkstra_time, reference_time
+    );
+
+    if mismatch_count > 0 {
+        anyhow::bail!(
+            "The index or Dijkstra disagrees with the reference search for {} of {} targets",
+            mismatch_count,
+            target_count
+        );
+    }
+
+    info!("The index and Dijkstra agree with the reference search for all targets");
+    Ok(())
+}
+
+/// Computes the distances from the source to the targets with a label-correcting search in the style of Bellman-Ford,
+/// which serves as a reference that shares no code with the Dijkstra searches.
+///
+/// The search state is a directed node together with the distance from the start of the source node to its start.
+/// States are relaxed until no edge improves them.
+/// Below the source offset, each distinct distance is its own state, since whether a location of the node lies after the source depends on it.
+/// At or beyond the source offset, only the smallest distance of each node is kept.
+/// The source node itself is a state with distance zero, which yields the distances to the targets after the source in the source node.
+fn reference_distances<
+    IndexType: GraphIndexInteger,
+    NodeData: GfaNodeData,
+    EdgeData: GfaEdgeData,
+>(
+    graph: &BidirectedAdjacencyArray<IndexType, NodeData, EdgeData>,
+    source: GfaLocation<IndexType>,
+    targets: &[GfaLocation<IndexType>],
+) -> Vec<Option<GfaPathLength<IndexType>>> {
+    let source_offset = source.offset().into_usize();
+    let mut before_source = vec![BTreeSet::new(); graph.node_count() * 2];
+    let mut past_source = vec![None; graph.node_count() * 2];
+    // The states that may improve the known states, which are relaxed in the order in which they were found.
+    let mut queue = VecDeque::from([(source.node(), 0)]);
+    while let Some((node, distance)) = queue.pop_front() {
+        let improved = if distance < source_offset {
+            before_source[node.into_usize()].insert(distance)
+        } else if past_source[node.into_usize()].is_none_or(|known| distance < known) {
+            past_source[node.into_usize()] = Some(distance);
+            true
+        } else {
+            false
+        };
+
+        if improved {
+            for outgoing_edge in graph.iter_outgoing_edges(node) {
+                queue.push_back((
+                    outgoing_edge.to(),
+                    distance + edge_length(graph, &outgoing_edge).into_usize(),
+                ));
+            }
+        }
+    }
+
+    targets
+        .iter()
+        .map(|target| {
+            let node = target.node().into_usize();
+            before_source[node]
+                .iter()
+                .chain(&past_source[node])
+                .filter_map(|distance| {
+                    (distance + target.offset().into_usize()).checked_sub(source_offset)
+                })
+                .min()
+                .map(GfaPathLength::from_usize)
+        })
+        .collect()
+}
+
+fn format_distance(distance: Option<impl ToString>) -> String {
+    distance.map_or_else(|| "None".to_string(), |distance| distance.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    const TINY1_LN_GFA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1_ln.gfa");
+    const TINY1_OVERLAPS_GFA: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1_overlaps.gfa");
+    const TINY1_QUERY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/tiny1.query");
+    const OVERLAP_BEFORE_SOURCE_GFA: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_files/overlap_before_source.gfa"
+    );
+    const OVERLAP_BEFORE_SOURCE_QUERY: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_files/overlap_before_source.query"
+    );
+
+    /// Builds an index of the graph and verifies it against the reference search with the query file and with random queries.
+    fn index_agrees_with_reference(name: &str, graph_gfa_in: &str, query_in: &str) {
+        let directory =
+            std::env::temp_dir().join(format!("biopath-test-verify-{name}-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let index_out = directory.join("index").to_str().unwrap().to_string();
+
+        crate::index::run(crate::index::Cli::parse_from([
+            "index",
+            "--graph-gfa-in",
+            graph_gfa_in,
+            "--index-out",
+            &index_out,
+        ]))
+        .unwrap();
+        super::run(super::Cli::parse_from([
+            "verify",
+            "--graph-gfa-in",
+            graph_gfa_in,
+            "--index-in",
+            &index_out,
+            "--query-in",
+            query_in,
+        ]))
+        .unwrap();
+        super::run(super::Cli::parse_from([
+            "verify",
+            "--graph-gfa-in",
+            graph_gfa_in,
+            "--index-in",
+            &index_out,
+            "--random-queries",
+            "1000",
+            "--threads",
+            "2",
+        ]))
+        .unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn index_agrees_with_reference_without_overlaps() {
+        index_agrees_with_reference("tiny1", TINY1_LN_GFA, TINY1_QUERY);
+    }
+
+    #[test]
+    fn index_agrees_with_reference_with_overlaps() {
+        index_agrees_with_reference("tiny1-overlaps", TINY1_OVERLAPS_GFA, TINY1_QUERY);
+    }
+
+    /// Walks that reach a node before passing the source offset may need to be longer than the shortest walk to that node.
+    #[test]
+    fn index_agrees_with_reference_with_overlaps_before_the_source() {
+        index_agrees_with_reference(
+            "overlap-before-source",
+            OVERLAP_BEFORE_SOURCE_GFA,
+            OVERLAP_BEFORE_SOURCE_QUERY,
+        );
+    }
+}
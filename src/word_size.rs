@@ -0,0 +1,183 @@
+use std::{io::BufRead, path::Path};
+
+use anyhow::Context;
+use log::info;
+
+use crate::io_util::{make_rereadable, read_optionally_compressed_file};
+
+/// The supported word sizes in bits, in increasing order.
+const SUPPORTED_WORD_SIZES: [u8; 4] = [8, 16, 32, 64];
+
+/// The sizes of a GFA file that determine which word size is required to load it.
+#[derive(Debug, Default)]
+struct GfaSizes {
+    node_count: u64,
+    edge_count: u64,
+    total_sequence_length: u64,
+}
+
+/// Selects the word size for loading the graph from the given GFA file.
+///
+/// The GFA file is scanned to make sure that the word size fits the graph.
+/// If no word size is given, then the smallest fitting word size is selected.
+/// Otherwise, an error is returned if the given word size is unsupported or too small.
+pub fn select_word_size(graph_gfa_in: &Path, word_size: Option<u8>) -> anyhow::Result<u8> {
+    if let Some(word_size) = word_size
+        && !SUPPORTED_WORD_SIZES.contains(&word_size)
+    {
+        anyhow::bail!("Unsupported word size: {word_size}. Supported are 8, 16, 32 and 64.");
+    }
+
+    // The GFA file is read again after scanning it.
+    make_rereadable(graph_gfa_in)?;
+    info!(
+        "Scanning GFA file {:?} to select the word size",
+        graph_gfa_in
+    );
+    let sizes = read_optionally_compressed_file(graph_gfa_in, |reader| {
+        GfaSizes::scan(reader)
+            .with_context(|| format!("Failed to scan GFA file {:?}", graph_gfa_in))
+    })
+    .with_context(|| format!("Failed to read GFA file: {:?}", graph_gfa_in))?;
+    let required_word_size = sizes.required_word_size().with_context(|| {
+        format!(
+            "The graph in GFA file {:?} is too large for the largest supported word size: {sizes:?}",
+            graph_gfa_in
+        )
+    })?;
+
+    if let Some(word_size) = word_size {
+        if word_size < required_word_size {
+            anyhow::bail!(
+                "Word size {word_size} is too small for the graph in GFA file {:?}, \
+                 which requires at least {required_word_size}: {sizes:?}",
+                graph_gfa_in
+            );
+        }
+        Ok(word_size)
+    } else {
+        info!("Selected word size {required_word_size}");
+        Ok(required_word_size)
+    }
+}
+
+impl GfaSizes {
+    /// Counts the segments and links or edges, and sums up the lengths of the segments.
+    fn scan(mut reader: impl BufRead) -> std::io::Result<Self> {
+        let mut sizes = Self::default();
+        let mut line = Vec::new();
+
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            let mut columns = line.trim_ascii().split(|&byte| byte == b'\t');
+            match columns.next() {
+                Some(b"S") => {
+                    sizes.node_count += 1;
+                    // Segments without a sequence give their length in an `LN` tag, which is validated when loading the graph.
+                    // GFA2 segments give their length in the third column instead.
+                    let sequence_length = match columns.nth(1) {
+                        Some(length)
+                            if !length.is_empty() && length.iter().all(u8::is_ascii_digit) =>
+                        {
+                            str::from_utf8(length)
+                                .ok()
+                                .and_then(|length| length.parse().ok())
+                                .unwrap_or(u64::MAX)
+                        }
+                        Some(b"*") | Some(b"") | None => columns
+                            .find_map(|tag| tag.strip_prefix(b"LN:i:"))
+                            .and_then(|length| str::from_utf8(length).ok()?.parse().ok())
+                            .unwrap_or(0),
+                        Some(sequence) => sequence.len() as u64,
+                    };
+                    sizes.total_sequence_length =
+                        sizes.total_sequence_length.saturating_add(sequence_length);
+                }
+                // GFA2 edges include containments, which are not loaded, so this may overestimate the edge count.
+                Some(b"L") | Some(b"E") => sizes.edge_count += 1,
+                _ => {}
+            }
+            line.clear();
+        }
+
+        Ok(sizes)
+    }
+
+    /// Returns the smallest supported word size that fits the graph, or `None` if none fits.
+    ///
+    /// Each bidirected node and edge is stored as two directed ones.
+    /// Path lengths use the same word size, and twice the total sequence length bounds the length of any path
+    /// that visits each node at most once in each orientation, as well as the length of each node.
+    fn required_word_size(&self) -> Option<u8> {
+        let max_value = self
+            .node_count
+            .saturating_mul(2)
+            .max(self.edge_count.saturating_mul(2))
+            .max(self.total_sequence_length.saturating_mul(2));
+        SUPPORTED_WORD_SIZES
+            .into_iter()
+            .find(|&word_size| fits_into_word_size(max_value, word_size))
+    }
+}
+
+/// Returns true if the value can be stored with the word size.
+/// The largest value of each word size is reserved to mark the absence of a value.
+pub fn fits_into_word_size(value: u64, word_size: u8) -> bool {
+    value < u64::MAX >> (64 - u32::from(word_size))
+}
+
+/// Returns the next larger supported word size, if any.
+pub fn next_larger_word_size(word_size: u8) -> Option<u8> {
+    SUPPORTED_WORD_SIZES
+        .into_iter()
+        .find(|&larger_word_size| larger_word_size > word_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_counts_segments_and_edges() {
+        let sizes = GfaSizes::scan(
+            b"H\tVN:Z:1.0\n\
+              S\ta\tACGT\n\
+              S\tb\t*\tLN:i:10\n\
+              S\tc\t7\t*\n\
+              S\td\t*\n\
+              L\ta\t+\tb\t-\t0M\n\
+              E\te\tb+\tc+\t10$\t10$\t0\t0\t*\n\
+              P\tp\ta+,b-\t*\n"
+                .as_slice(),
+        )
+        .unwrap();
+        assert_eq!(sizes.node_count, 4);
+        assert_eq!(sizes.edge_count, 2);
+        assert_eq!(sizes.total_sequence_length, 4 + 10 + 7);
+    }
+
+    #[test]
+    fn required_word_size_reserves_the_largest_value() {
+        let sizes = |node_count, edge_count, total_sequence_length| GfaSizes {
+            node_count,
+            edge_count,
+            total_sequence_length,
+        };
+        assert_eq!(sizes(127, 127, 127).required_word_size(), Some(8));
+        assert_eq!(sizes(128, 0, 0).required_word_size(), Some(16));
+        assert_eq!(sizes(0, 128, 0).required_word_size(), Some(16));
+        assert_eq!(sizes(0, 0, 128).required_word_size(), Some(16));
+        assert_eq!(sizes(0, 0, 1 << 31).required_word_size(), Some(64));
+        assert_eq!(sizes(u64::MAX / 2 + 1, 0, 0).required_word_size(), None);
+    }
+
+    #[test]
+    fn word_size_helpers() {
+        assert!(fits_into_word_size(254, 8));
+        assert!(!fits_into_word_size(255, 8));
+        assert!(fits_into_word_size(u64::MAX - 1, 64));
+        assert!(!fits_into_word_size(u64::MAX, 64));
+        assert_eq!(next_larger_word_size(8), Some(16));
+        assert_eq!(next_larger_word_size(32), Some(64));
+        assert_eq!(next_larger_word_size(64), None);
+    }
+}